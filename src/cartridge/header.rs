@@ -0,0 +1,369 @@
+pub const HEADER_BEGIN: usize = 0x0100;
+pub const HEADER_END: usize = 0x014F;
+
+pub const LOGO_BEGIN: usize = 0x0104;
+pub const LOGO_END: usize = 0x0133;
+pub const TITLE_BEGIN: usize = 0x0134;
+pub const TITLE_END: usize = 0x0143;
+pub const MANUFACTURER_CODE_BEGIN: usize = 0x013F;
+pub const MANUFACTURER_CODE_END: usize = 0x0142;
+pub const CGB_FLAG: usize = 0x0143;
+pub const NEW_LICENSEE_CODE_BEGIN: usize = 0x0144;
+pub const NEW_LICENSEE_CODE_END: usize = 0x0145;
+pub const SGB_FLAG: usize = 0x0146;
+pub const CARTRIDGE_TYPE: usize = 0x0147;
+pub const ROM_SIZE: usize = 0x0148;
+pub const RAM_SIZE: usize = 0x0149;
+pub const DESTINATION_CODE: usize = 0x014A;
+pub const OLD_LICENSEE_CODE: usize = 0x014B;
+pub const VERSION: usize = 0x014C;
+pub const HEADER_CHECKSUM: usize = 0x014D;
+pub const GLOBAL_CHECKSUM_BEGIN: usize = 0x014E;
+pub const GLOBAL_CHECKSUM_END: usize = 0x014F;
+
+// An old licensee code of 0x33 means the new licensee code is used instead
+const USE_NEW_LICENSEE_CODE: u8 = 0x33;
+
+// The boot ROM refuses to start a cartridge unless this is at 0x0104
+pub const NINTENDO_LOGO: [u8; LOGO_END - LOGO_BEGIN + 1] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgbSupport {
+    None,
+    // Runs on DMG too but uses CGB features when available
+    Enhanced,
+    Only,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Destination {
+    Japan,
+    Overseas,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Licensee {
+    Old(u8),
+    New([u8; 2]),
+}
+
+impl Licensee {
+    pub fn is_nintendo(&self) -> bool {
+        matches!(self, Licensee::Old(0x01) | Licensee::New([b'0', b'1']))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mapper {
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    Mmm01,
+    PocketCamera,
+    BandaiTama5,
+    HuC1,
+    HuC3,
+    Unknown(u8),
+}
+
+// The mapper plus the extra hardware on the cartridge, decoded from the byte at 0x0147
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CartridgeType {
+    pub mapper: Mapper,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+    pub sensor: bool,
+}
+
+impl std::convert::From<u8> for CartridgeType {
+    fn from(byte: u8) -> Self {
+        let (mapper, ram, battery, timer, rumble, sensor) = match byte {
+            0x00 => (Mapper::RomOnly, false, false, false, false, false),
+            0x01 => (Mapper::Mbc1, false, false, false, false, false),
+            0x02 => (Mapper::Mbc1, true, false, false, false, false),
+            0x03 => (Mapper::Mbc1, true, true, false, false, false),
+            0x05 => (Mapper::Mbc2, false, false, false, false, false),
+            0x06 => (Mapper::Mbc2, false, true, false, false, false),
+            0x08 => (Mapper::RomOnly, true, false, false, false, false),
+            0x09 => (Mapper::RomOnly, true, true, false, false, false),
+            0x0B => (Mapper::Mmm01, false, false, false, false, false),
+            0x0C => (Mapper::Mmm01, true, false, false, false, false),
+            0x0D => (Mapper::Mmm01, true, true, false, false, false),
+            0x0F => (Mapper::Mbc3, false, true, true, false, false),
+            0x10 => (Mapper::Mbc3, true, true, true, false, false),
+            0x11 => (Mapper::Mbc3, false, false, false, false, false),
+            0x12 => (Mapper::Mbc3, true, false, false, false, false),
+            0x13 => (Mapper::Mbc3, true, true, false, false, false),
+            0x19 => (Mapper::Mbc5, false, false, false, false, false),
+            0x1A => (Mapper::Mbc5, true, false, false, false, false),
+            0x1B => (Mapper::Mbc5, true, true, false, false, false),
+            0x1C => (Mapper::Mbc5, false, false, false, true, false),
+            0x1D => (Mapper::Mbc5, true, false, false, true, false),
+            0x1E => (Mapper::Mbc5, true, true, false, true, false),
+            0x20 => (Mapper::Mbc6, false, false, false, false, false),
+            0x22 => (Mapper::Mbc7, true, true, false, true, true),
+            0xFC => (Mapper::PocketCamera, false, false, false, false, false),
+            0xFD => (Mapper::BandaiTama5, false, false, false, false, false),
+            0xFE => (Mapper::HuC3, false, false, false, false, false),
+            0xFF => (Mapper::HuC1, true, true, false, false, false),
+            _ => (Mapper::Unknown(byte), false, false, false, false, false),
+        };
+
+        CartridgeType {
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+            sensor,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeaderValidation {
+    pub logo: bool,
+    pub header_checksum: bool,
+    // Real hardware never checks this one, plenty of homebrew gets it wrong
+    pub global_checksum: bool,
+}
+
+impl HeaderValidation {
+    // Whether the boot ROM would accept this cartridge
+    pub fn is_bootable(&self) -> bool {
+        self.logo && self.header_checksum
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
+    pub cartridge_type: CartridgeType,
+    // In bytes, None when the header uses a code that isn't known
+    pub rom_size: Option<usize>,
+    pub ram_size: Option<usize>,
+    pub destination: Destination,
+    pub licensee: Licensee,
+    pub version: u8,
+    // Sum of the 16 title bytes, the CGB boot ROM uses it to pick colours for DMG games
+    pub title_checksum: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    pub validation: HeaderValidation,
+}
+
+impl CartridgeHeader {
+    // Returns None if the ROM is too short to contain a header
+    pub fn parse(rom: &[u8]) -> Option<CartridgeHeader> {
+        if rom.len() <= HEADER_END {
+            return None;
+        }
+
+        let cgb_support = match rom[CGB_FLAG] {
+            0x80 => CgbSupport::Enhanced,
+            0xC0 => CgbSupport::Only,
+            _ => CgbSupport::None,
+        };
+
+        // Newer cartridges shortened the title to make room for the manufacturer code
+        // and CGB flag, the code is only there if it looks like four upper case characters
+        let manufacturer_code = &rom[MANUFACTURER_CODE_BEGIN..=MANUFACTURER_CODE_END];
+        let has_manufacturer_code = cgb_support != CgbSupport::None
+            && manufacturer_code
+                .iter()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+        let title_end = if has_manufacturer_code {
+            MANUFACTURER_CODE_BEGIN - 1
+        } else if cgb_support != CgbSupport::None {
+            CGB_FLAG - 1
+        } else {
+            TITLE_END
+        };
+
+        let licensee = match rom[OLD_LICENSEE_CODE] {
+            USE_NEW_LICENSEE_CODE => Licensee::New([
+                rom[NEW_LICENSEE_CODE_BEGIN],
+                rom[NEW_LICENSEE_CODE_END],
+            ]),
+            code => Licensee::Old(code),
+        };
+
+        let header_checksum = rom[HEADER_CHECKSUM];
+        let global_checksum = ((rom[GLOBAL_CHECKSUM_BEGIN] as u16) << 8) | rom[GLOBAL_CHECKSUM_END] as u16;
+
+        Some(CartridgeHeader {
+            title: ascii_string(&rom[TITLE_BEGIN..=title_end]),
+            manufacturer_code: if has_manufacturer_code {
+                Some(ascii_string(manufacturer_code))
+            } else {
+                None
+            },
+            cgb_support,
+            sgb_support: rom[SGB_FLAG] == 0x03,
+            cartridge_type: CartridgeType::from(rom[CARTRIDGE_TYPE]),
+            rom_size: rom_size_from_code(rom[ROM_SIZE]),
+            ram_size: ram_size_from_code(rom[RAM_SIZE]),
+            destination: match rom[DESTINATION_CODE] {
+                0x00 => Destination::Japan,
+                0x01 => Destination::Overseas,
+                code => Destination::Unknown(code),
+            },
+            licensee,
+            version: rom[VERSION],
+            title_checksum: compute_title_checksum(rom),
+            header_checksum,
+            global_checksum,
+            validation: HeaderValidation {
+                logo: rom[LOGO_BEGIN..=LOGO_END] == NINTENDO_LOGO[..],
+                header_checksum: compute_header_checksum(rom) == header_checksum,
+                global_checksum: compute_global_checksum(rom) == global_checksum,
+            },
+        })
+    }
+}
+
+// Sums the whole title area, including the manufacturer code and CGB flag if there are any
+pub fn compute_title_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE_BEGIN..=TITLE_END]
+        .iter()
+        .fold(0u8, |checksum, byte| checksum.wrapping_add(*byte))
+}
+
+// Computed the same way the boot ROM does over 0x0134-0x014C
+pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE_BEGIN..=VERSION]
+        .iter()
+        .fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1))
+}
+
+// Sum of every byte in the ROM except the two checksum bytes themselves
+pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(address, _)| !(GLOBAL_CHECKSUM_BEGIN..=GLOBAL_CHECKSUM_END).contains(address))
+        .fold(0u16, |checksum, (_, byte)| checksum.wrapping_add(*byte as u16))
+}
+
+fn rom_size_from_code(code: u8) -> Option<usize> {
+    match code {
+        0x00..=0x08 => Some((32 * 1024) << code),
+        _ => None,
+    }
+}
+
+fn ram_size_from_code(code: u8) -> Option<usize> {
+    match code {
+        0x00 => Some(0),
+        0x01 => Some(2 * 1024),
+        0x02 => Some(8 * 1024),
+        0x03 => Some(32 * 1024),
+        0x04 => Some(128 * 1024),
+        0x05 => Some(64 * 1024),
+        _ => None,
+    }
+}
+
+fn ascii_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|byte| **byte != 0)
+        .map(|byte| *byte as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_with_header(title: &[u8], cgb_flag: u8, cartridge_type: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[LOGO_BEGIN..=LOGO_END].copy_from_slice(&NINTENDO_LOGO);
+        rom[TITLE_BEGIN..TITLE_BEGIN + title.len()].copy_from_slice(title);
+        rom[CGB_FLAG] = cgb_flag;
+        rom[CARTRIDGE_TYPE] = cartridge_type;
+        rom[ROM_SIZE] = 0x00;
+        rom[RAM_SIZE] = 0x02;
+        rom[DESTINATION_CODE] = 0x01;
+        rom[OLD_LICENSEE_CODE] = 0x01;
+        rom[HEADER_CHECKSUM] = compute_header_checksum(&rom);
+        let global_checksum = compute_global_checksum(&rom);
+        rom[GLOBAL_CHECKSUM_BEGIN] = (global_checksum >> 8) as u8;
+        rom[GLOBAL_CHECKSUM_END] = global_checksum as u8;
+        rom
+    }
+
+    #[test]
+    fn test_parse_dmg_header() {
+        let rom = rom_with_header(b"TETRIS", 0x00, 0x03);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "TETRIS");
+        assert_eq!(header.manufacturer_code, None);
+        assert_eq!(header.cgb_support, CgbSupport::None);
+        assert_eq!(header.cartridge_type.mapper, Mapper::Mbc1);
+        assert!(header.cartridge_type.ram);
+        assert!(header.cartridge_type.battery);
+        assert_eq!(header.rom_size, Some(32 * 1024));
+        assert_eq!(header.ram_size, Some(8 * 1024));
+        assert_eq!(header.destination, Destination::Overseas);
+        assert_eq!(header.licensee, Licensee::Old(0x01));
+        assert!(header.licensee.is_nintendo());
+        assert_eq!(header.title_checksum, 0xDB);
+        assert!(header.validation.logo);
+        assert!(header.validation.header_checksum);
+        assert!(header.validation.global_checksum);
+        assert!(header.validation.is_bootable());
+    }
+
+    #[test]
+    fn test_parse_cgb_header_with_manufacturer_code() {
+        let rom = rom_with_header(b"POKEMON_GLDAAUE", 0x80, 0x10);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "POKEMON_GLD");
+        assert_eq!(header.manufacturer_code, Some("AAUE".to_string()));
+        assert_eq!(header.cgb_support, CgbSupport::Enhanced);
+        assert_eq!(header.cartridge_type.mapper, Mapper::Mbc3);
+        assert!(header.cartridge_type.timer);
+    }
+
+    #[test]
+    fn test_bad_checksums_and_logo_are_reported() {
+        let mut rom = rom_with_header(b"BROKEN", 0x00, 0x00);
+        rom[LOGO_BEGIN] ^= 0xFF;
+        rom[VERSION] = 1;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(!header.validation.logo);
+        assert!(!header.validation.header_checksum);
+        assert!(!header.validation.global_checksum);
+        assert!(!header.validation.is_bootable());
+    }
+
+    #[test]
+    fn test_unknown_codes() {
+        let mut rom = rom_with_header(b"ODD", 0x00, 0x42);
+        rom[ROM_SIZE] = 0x52;
+        rom[RAM_SIZE] = 0x09;
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.cartridge_type.mapper, Mapper::Unknown(0x42));
+        assert_eq!(header.rom_size, None);
+        assert_eq!(header.ram_size, None);
+    }
+
+    #[test]
+    fn test_short_rom_has_no_header() {
+        assert_eq!(CartridgeHeader::parse(&[0; 0x100]), None);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::cartridge::header::{LOGO_BEGIN, LOGO_END, NINTENDO_LOGO};
+use crate::cartridge::{
+    read_banked, write_banked, Cartridge, DISCONNECTED_VALUE, RAM_BANK_SIZE, ROM_BANK_SIZE,
+};
+
+// MBC1M multicarts are 1 MiB and repeat the header, logo included, every 16 banks
+const MULTICART_ROM_SIZE: usize = 64 * ROM_BANK_SIZE;
+const MULTICART_GAME_BANKS: usize = 16;
+
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // 5 bit register at 0x2000-0x3FFF, lower bits of the ROM bank
+    bank1: u8,
+    // 2 bit register at 0x4000-0x5FFF, upper ROM bank bits or the RAM bank
+    bank2: u8,
+    // Mode 1 makes bank2 apply to 0x0000-0x3FFF and cartridge RAM as well
+    advanced_banking: bool,
+    // MBC1M only wires 4 bits of bank1, so bank2 starts at bit 4 instead of bit 5
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Mbc1 {
+        let multicart = is_multicart(&rom);
+        Mbc1 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            advanced_banking: false,
+            multicart,
+        }
+    }
+
+    pub fn is_multicart(&self) -> bool {
+        self.multicart
+    }
+
+    fn bank2_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn low_rom_bank(&self) -> usize {
+        if self.advanced_banking {
+            (self.bank2 << self.bank2_shift()) as usize
+        } else {
+            0
+        }
+    }
+
+    fn high_rom_bank(&self) -> usize {
+        let bank1 = if self.multicart {
+            self.bank1 & 0x0F
+        } else {
+            self.bank1
+        };
+        ((self.bank2 << self.bank2_shift()) | bank1) as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.advanced_banking {
+            self.bank2 as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl Cartridge for Mbc1 {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        let offset = address as usize & (ROM_BANK_SIZE - 1);
+        match address {
+            0x0000..=0x3FFF => read_banked(&self.rom, ROM_BANK_SIZE, self.low_rom_bank(), offset),
+            _ => read_banked(&self.rom, ROM_BANK_SIZE, self.high_rom_bank(), offset),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // The zero check happens on the full 5 bits, so bank 0x20 maps to 0x21 and so on
+                let bank = value & 0x1F;
+                self.bank1 = if bank == 0 { 1 } else { bank };
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.advanced_banking = value & 0x01 != 0,
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return DISCONNECTED_VALUE;
+        }
+        let offset = address as usize & (RAM_BANK_SIZE - 1);
+        read_banked(&self.ram, RAM_BANK_SIZE, self.ram_bank(), offset)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        let offset = address as usize & (RAM_BANK_SIZE - 1);
+        let bank = self.ram_bank();
+        write_banked(&mut self.ram, RAM_BANK_SIZE, bank, offset, value);
+    }
+}
+
+fn is_multicart(rom: &[u8]) -> bool {
+    if rom.len() != MULTICART_ROM_SIZE {
+        return false;
+    }
+    // Look for the logo at the start of the second game, a normal 1 MiB game won't have it
+    let logo_begin = MULTICART_GAME_BANKS * ROM_BANK_SIZE + LOGO_BEGIN;
+    let logo_end = MULTICART_GAME_BANKS * ROM_BANK_SIZE + LOGO_END;
+    rom[logo_begin..=logo_end] == NINTENDO_LOGO[..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every bank starts with its own bank number so reads show which one is mapped
+    fn numbered_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_bank_zero_maps_to_one() {
+        let mut mbc = Mbc1::new(numbered_rom(64), 0);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 5);
+        // Only the low 5 bits count, so 0x20 is treated as 0 and becomes 1
+        mbc.write_rom(0x2000, 0x20);
+        assert_eq!(mbc.read_rom(0x4000), 1);
+    }
+
+    #[test]
+    fn test_upper_bank_bits() {
+        let mut mbc = Mbc1::new(numbered_rom(128), 0);
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x00);
+        // 0x20 is unreachable in the upper area, it becomes 0x21
+        assert_eq!(mbc.read_rom(0x4000), 0x21);
+        mbc.write_rom(0x2000, 0x03);
+        assert_eq!(mbc.read_rom(0x4000), 0x23);
+        assert_eq!(mbc.read_rom(0x0000), 0);
+    }
+
+    #[test]
+    fn test_mode_1_remaps_low_area() {
+        let mut mbc = Mbc1::new(numbered_rom(128), 0);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x40);
+        mbc.write_rom(0x6000, 0x00);
+        assert_eq!(mbc.read_rom(0x0000), 0x00);
+    }
+
+    #[test]
+    fn test_bank_wraps_to_rom_size() {
+        let mut mbc = Mbc1::new(numbered_rom(4), 0);
+        mbc.write_rom(0x2000, 0x06);
+        assert_eq!(mbc.read_rom(0x4000), 2);
+    }
+
+    #[test]
+    fn test_ram_enable_and_banking() {
+        let mut mbc = Mbc1::new(numbered_rom(4), 4 * RAM_BANK_SIZE);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), DISCONNECTED_VALUE);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x12);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        // In mode 0 the RAM bank register is ignored
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_ram(0xA000, 0x34);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), 0x12);
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_ram(0xA000), 0x34);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(0xA000), DISCONNECTED_VALUE);
+    }
+
+    #[test]
+    fn test_multicart_detection_and_banking() {
+        let mut rom = numbered_rom(64);
+        let logo_begin = MULTICART_GAME_BANKS * ROM_BANK_SIZE + LOGO_BEGIN;
+        rom[logo_begin..logo_begin + NINTENDO_LOGO.len()].copy_from_slice(&NINTENDO_LOGO);
+        let mut mbc = Mbc1::new(rom, 0);
+        assert!(mbc.is_multicart());
+
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x12);
+        assert_eq!(mbc.read_rom(0x4000), 0x12);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x10);
+
+        assert!(!Mbc1::new(numbered_rom(64), 0).is_multicart());
+    }
+}
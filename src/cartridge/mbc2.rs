@@ -0,0 +1,100 @@
+use crate::cartridge::{read_banked, Cartridge, DISCONNECTED_VALUE, ROM_BANK_SIZE};
+
+// 512 half-byte cells built into the MBC2 itself
+pub const MBC2_RAM_SIZE: usize = 512;
+
+pub struct Mbc2 {
+    rom: Vec<u8>,
+    ram: [u8; MBC2_RAM_SIZE],
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new(rom: Vec<u8>) -> Mbc2 {
+        Mbc2 {
+            rom,
+            ram: [0; MBC2_RAM_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Cartridge for Mbc2 {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        let offset = address as usize & (ROM_BANK_SIZE - 1);
+        match address {
+            0x0000..=0x3FFF => read_banked(&self.rom, ROM_BANK_SIZE, 0, offset),
+            _ => read_banked(&self.rom, ROM_BANK_SIZE, self.rom_bank as usize, offset),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        // Only 0x0000-0x3FFF is decoded, address bit 8 picks the register
+        if address > 0x3FFF {
+            return;
+        }
+        if address & 0x0100 == 0 {
+            self.ram_enabled = value & 0x0F == 0x0A;
+        } else {
+            let bank = value & 0x0F;
+            self.rom_bank = if bank == 0 { 1 } else { bank };
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return DISCONNECTED_VALUE;
+        }
+        // Only the low nibble is stored, and the 512 cells repeat across the whole area
+        self.ram[address as usize & (MBC2_RAM_SIZE - 1)] | 0xF0
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_enabled {
+            self.ram[address as usize & (MBC2_RAM_SIZE - 1)] = value & 0x0F;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_selected_by_address_bit_8() {
+        let mut rom = vec![0; 16 * ROM_BANK_SIZE];
+        rom[5 * ROM_BANK_SIZE] = 5;
+        let mut mbc = Mbc2::new(rom);
+        mbc.write_rom(0x2100, 0x05);
+        assert_eq!(mbc.read_rom(0x4000), 5);
+        // Bit 8 clear goes to RAM enable, not the bank
+        mbc.write_rom(0x2000, 0x0A);
+        assert_eq!(mbc.read_rom(0x4000), 5);
+        mbc.write_rom(0x0100, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0);
+        assert_eq!(mbc.read_rom(0x4001), 0);
+    }
+
+    #[test]
+    fn test_half_byte_ram_echoes() {
+        let mut mbc = Mbc2::new(vec![0; 2 * ROM_BANK_SIZE]);
+        mbc.write_ram(0xA000, 0x0C);
+        assert_eq!(mbc.read_ram(0xA000), DISCONNECTED_VALUE);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA010, 0xAB);
+        assert_eq!(mbc.read_ram(0xA010), 0xFB);
+        assert_eq!(mbc.read_ram(0xA210), 0xFB);
+        assert_eq!(mbc.read_ram(0xBE10), 0xFB);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::cartridge::rtc::{Rtc, RTC_FOOTER_SIZE, SHORT_RTC_FOOTER_SIZE};
+use crate::cartridge::{
+    load_ram, read_banked, write_banked, Cartridge, DISCONNECTED_VALUE, RAM_BANK_SIZE,
+    ROM_BANK_SIZE,
+};
+use crate::error::EmulationError;
+
+const RTC_SECONDS: u8 = 0x08;
+const RTC_DAY_HIGH: u8 = 0x0C;
+
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rtc: Option<Rtc>,
+    // Enables both the RAM and the RTC registers
+    ram_enabled: bool,
+    rom_bank: u8,
+    // 0x00-0x07 picks a RAM bank, 0x08-0x0C maps an RTC register instead
+    ram_bank: u8,
+    // Latching needs a 0 followed by a 1
+    latch_armed: bool,
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, rtc: Option<Rtc>) -> Mbc3 {
+        Mbc3 {
+            rom,
+            ram: vec![0; ram_size],
+            rtc,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            latch_armed: false,
+        }
+    }
+
+    pub fn rtc(&self) -> Option<&Rtc> {
+        self.rtc.as_ref()
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
+    fn rtc_selected(&self) -> bool {
+        (RTC_SECONDS..=RTC_DAY_HIGH).contains(&self.ram_bank)
+    }
+}
+
+impl Cartridge for Mbc3 {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        let offset = address as usize & (ROM_BANK_SIZE - 1);
+        match address {
+            0x0000..=0x3FFF => read_banked(&self.rom, ROM_BANK_SIZE, 0, offset),
+            _ => read_banked(&self.rom, ROM_BANK_SIZE, self.rom_bank as usize, offset),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                let bank = value & 0x7F;
+                self.rom_bank = if bank == 0 { 1 } else { bank };
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {
+                if self.latch_armed && value == 0x01 {
+                    if let Some(rtc) = self.rtc.as_mut() {
+                        rtc.latch();
+                    }
+                }
+                self.latch_armed = value == 0x00;
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return DISCONNECTED_VALUE;
+        }
+        if self.rtc_selected() {
+            return match &self.rtc {
+                Some(rtc) => rtc.read(self.ram_bank),
+                None => DISCONNECTED_VALUE,
+            };
+        }
+        if self.ram.is_empty() {
+            return DISCONNECTED_VALUE;
+        }
+        let offset = address as usize & (RAM_BANK_SIZE - 1);
+        read_banked(&self.ram, RAM_BANK_SIZE, self.ram_bank as usize, offset)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        if self.rtc_selected() {
+            if let Some(rtc) = self.rtc.as_mut() {
+                rtc.write(self.ram_bank, value);
+            }
+            return;
+        }
+        let offset = address as usize & (RAM_BANK_SIZE - 1);
+        let bank = self.ram_bank as usize;
+        write_banked(&mut self.ram, RAM_BANK_SIZE, bank, offset, value);
+    }
+
+    fn save_data(&mut self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let Some(rtc) = self.rtc.as_mut() {
+            data.extend_from_slice(&rtc.footer());
+        }
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) -> Result<(), EmulationError> {
+        let ram_size = self.ram.len();
+        // Saves from emulators without RTC support are just the RAM, so the footer is optional
+        match (self.rtc.as_mut(), data.len().checked_sub(ram_size)) {
+            (Some(rtc), Some(RTC_FOOTER_SIZE)) | (Some(rtc), Some(SHORT_RTC_FOOTER_SIZE)) => {
+                rtc.load_footer(&data[ram_size..]);
+                load_ram(&mut self.ram, &data[..ram_size])
+            }
+            _ => load_ram(&mut self.ram, data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::rtc::tests::ManualClock;
+
+    fn numbered_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_seven_bit_rom_bank() {
+        let mut mbc = Mbc3::new(numbered_rom(128), 0, None);
+        mbc.write_rom(0x2000, 0x7F);
+        assert_eq!(mbc.read_rom(0x4000), 0x7F);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x01);
+        assert_eq!(mbc.read_rom(0x0000), 0x00);
+    }
+
+    #[test]
+    fn test_ram_banks() {
+        let mut mbc = Mbc3::new(numbered_rom(2), 4 * RAM_BANK_SIZE, None);
+        mbc.write_rom(0x0000, 0x0A);
+        for bank in 0..4 {
+            mbc.write_rom(0x4000, bank);
+            mbc.write_ram(0xA123, 0x10 + bank);
+        }
+        for bank in 0..4 {
+            mbc.write_rom(0x4000, bank);
+            assert_eq!(mbc.read_ram(0xA123), 0x10 + bank);
+        }
+    }
+
+    #[test]
+    fn test_rtc_mapped_and_latched() {
+        let clock = ManualClock::default();
+        let rtc = Rtc::new(Box::new(clock.clone()));
+        let mut mbc = Mbc3::new(numbered_rom(2), RAM_BANK_SIZE, Some(rtc));
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+        clock.advance(42);
+
+        // A lone 1 doesn't latch
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 0);
+
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 42);
+
+        mbc.write_rom(0x4000, 0x09);
+        mbc.write_ram(0xA000, 10);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(0xA000), 10);
+    }
+
+    #[test]
+    fn test_rtc_select_without_rtc_reads_disconnected() {
+        let mut mbc = Mbc3::new(numbered_rom(2), RAM_BANK_SIZE, None);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+        assert_eq!(mbc.read_ram(0xA000), DISCONNECTED_VALUE);
+    }
+
+    #[test]
+    fn test_save_data_round_trip_with_rtc_footer() {
+        let clock = ManualClock::default();
+        clock.advance(1_000_000);
+        let rtc = Rtc::new(Box::new(clock.clone()));
+        let mut mbc = Mbc3::new(numbered_rom(2), RAM_BANK_SIZE, Some(rtc));
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x99);
+        mbc.write_rom(0x4000, 0x0A);
+        mbc.write_ram(0xA000, 5);
+
+        let data = mbc.save_data();
+        assert_eq!(data.len(), RAM_BANK_SIZE + RTC_FOOTER_SIZE);
+
+        // An hour passes while the game is off
+        clock.advance(3600);
+        let rtc = Rtc::new(Box::new(clock.clone()));
+        let mut restored = Mbc3::new(numbered_rom(2), RAM_BANK_SIZE, Some(rtc));
+        restored.load_save_data(&data).unwrap();
+        restored.write_rom(0x0000, 0x0A);
+        assert_eq!(restored.read_ram(0xA000), 0x99);
+        restored.write_rom(0x6000, 0x00);
+        restored.write_rom(0x6000, 0x01);
+        restored.write_rom(0x4000, 0x0A);
+        assert_eq!(restored.read_ram(0xA000), 6);
+    }
+
+    #[test]
+    fn test_save_data_without_footer() {
+        let rtc = Rtc::new(Box::new(ManualClock::default()));
+        let mut mbc = Mbc3::new(numbered_rom(2), RAM_BANK_SIZE, Some(rtc));
+        assert!(mbc.load_save_data(&[0x11; RAM_BANK_SIZE]).is_ok());
+        assert!(mbc.load_save_data(&[0x11; 100]).is_err());
+    }
+}
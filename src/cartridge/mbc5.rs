@@ -0,0 +1,158 @@
+use crate::cartridge::{
+    read_banked, write_banked, Cartridge, CartridgeEvent, DISCONNECTED_VALUE, RAM_BANK_SIZE,
+    ROM_BANK_SIZE,
+};
+
+// On rumble cartridges bit 3 of the RAM bank register drives the motor instead
+const RUMBLE_BIT: u8 = 0b0000_1000;
+
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // 9 bits, unlike the other MBCs bank 0 can be mapped to 0x4000-0x7FFF
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble_on: bool,
+    // Games drive the motor with PWM, so only the latest state is kept until the host polls
+    pending_event: Option<CartridgeEvent>,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Mbc5 {
+        Mbc5 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble_on: false,
+            pending_event: None,
+        }
+    }
+}
+
+impl Cartridge for Mbc5 {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        let offset = address as usize & (ROM_BANK_SIZE - 1);
+        match address {
+            0x0000..=0x3FFF => read_banked(&self.rom, ROM_BANK_SIZE, 0, offset),
+            _ => read_banked(&self.rom, ROM_BANK_SIZE, self.rom_bank as usize, offset),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8),
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.ram_bank = value & 0x07;
+                    let rumble_on = value & RUMBLE_BIT != 0;
+                    if rumble_on != self.rumble_on {
+                        self.rumble_on = rumble_on;
+                        self.pending_event = Some(CartridgeEvent::Rumble(rumble_on));
+                    }
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return DISCONNECTED_VALUE;
+        }
+        let offset = address as usize & (RAM_BANK_SIZE - 1);
+        read_banked(&self.ram, RAM_BANK_SIZE, self.ram_bank as usize, offset)
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        let offset = address as usize & (RAM_BANK_SIZE - 1);
+        let bank = self.ram_bank as usize;
+        write_banked(&mut self.ram, RAM_BANK_SIZE, bank, offset, value);
+    }
+
+    fn poll_event(&mut self) -> Option<CartridgeEvent> {
+        self.pending_event.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered_rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+            rom[bank * ROM_BANK_SIZE + 1] = (bank >> 8) as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_nine_bit_rom_bank() {
+        let mut mbc = Mbc5::new(numbered_rom(512), 0, false);
+        mbc.write_rom(0x2000, 0x23);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(0x4000), 0x23);
+        assert_eq!(mbc.read_rom(0x4001), 0x01);
+        // Bank 0 is a valid choice on MBC5
+        mbc.write_rom(0x2000, 0x00);
+        mbc.write_rom(0x3000, 0x00);
+        assert_eq!(mbc.read_rom(0x4000), 0x00);
+    }
+
+    #[test]
+    fn test_sixteen_ram_banks() {
+        let mut mbc = Mbc5::new(numbered_rom(2), 16 * RAM_BANK_SIZE, false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(0xA000, 0x5F);
+        mbc.write_rom(0x4000, 0x07);
+        assert_eq!(mbc.read_ram(0xA000), 0x00);
+        mbc.write_rom(0x4000, 0x0F);
+        assert_eq!(mbc.read_ram(0xA000), 0x5F);
+    }
+
+    #[test]
+    fn test_rumble_events_on_change() {
+        let mut mbc = Mbc5::new(numbered_rom(2), 8 * RAM_BANK_SIZE, true);
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_rom(0x4000, 0x09);
+        assert_eq!(mbc.poll_event(), Some(CartridgeEvent::Rumble(true)));
+        assert_eq!(mbc.poll_event(), None);
+
+        // Toggling faster than the host polls only reports where the motor ended up
+        for _ in 0..1000 {
+            mbc.write_rom(0x4000, 0x00);
+            mbc.write_rom(0x4000, 0x08);
+        }
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.poll_event(), Some(CartridgeEvent::Rumble(false)));
+        assert_eq!(mbc.poll_event(), None);
+
+        // The motor bit doesn't reach the RAM bank
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(0xA000, 0x11);
+        mbc.write_rom(0x4000, 0x09);
+        assert_eq!(mbc.read_ram(0xA000), 0x11);
+    }
+}
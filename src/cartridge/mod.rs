@@ -0,0 +1,112 @@
+pub mod header;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod rom_only;
+pub mod rtc;
+
+use crate::cartridge::header::{CartridgeHeader, Mapper};
+use crate::cartridge::mbc1::Mbc1;
+use crate::cartridge::mbc2::Mbc2;
+use crate::cartridge::mbc3::Mbc3;
+use crate::cartridge::mbc5::Mbc5;
+use crate::cartridge::rom_only::RomOnly;
+use crate::cartridge::rtc::{Clock, Rtc, SystemClock};
+use crate::error::EmulationError;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+// What a disabled or missing chip reads back as
+pub const DISCONNECTED_VALUE: u8 = 0xFF;
+
+// Everything the bus sees of a cartridge. Addresses are the CPU addresses, so ROM
+// accesses are in 0x0000-0x7FFF and RAM accesses in 0xA000-0xBFFF. Writes to the
+// ROM area never change the ROM, they go to the mapper's control registers.
+pub trait Cartridge {
+    fn read_rom(&self, address: u16) -> u8;
+    fn write_rom(&mut self, address: u16, value: u8);
+    fn read_ram(&self, address: u16) -> u8;
+    fn write_ram(&mut self, address: u16, value: u8);
+
+    // The external RAM as a flat array of all its banks
+    fn ram(&self) -> &[u8];
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    // Battery backed state in the usual .sav layout, the raw RAM followed by
+    // anything else the cartridge keeps powered
+    fn save_data(&mut self) -> Vec<u8> {
+        self.ram().to_vec()
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) -> Result<(), EmulationError> {
+        load_ram(self.ram_mut(), data)
+    }
+
+    // Things the host should know about that happen outside the emulated screen
+    fn poll_event(&mut self) -> Option<CartridgeEvent> {
+        None
+    }
+}
+
+pub fn load_ram(ram: &mut [u8], data: &[u8]) -> Result<(), EmulationError> {
+    if data.len() != ram.len() {
+        return Err(EmulationError::SaveDataSize {
+            actual: data.len(),
+            expected: ram.len(),
+        });
+    }
+    ram.copy_from_slice(data);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CartridgeEvent {
+    // The rumble motor was switched on (true) or off (false)
+    Rumble(bool),
+}
+
+// Picks the mapper the header asks for, any RTC follows the host's clock
+pub fn from_rom(rom: Vec<u8>, header: &CartridgeHeader) -> Result<Box<dyn Cartridge>, EmulationError> {
+    from_rom_with_clock(rom, header, Box::new(SystemClock))
+}
+
+pub fn from_rom_with_clock(
+    rom: Vec<u8>,
+    header: &CartridgeHeader,
+    clock: Box<dyn Clock>,
+) -> Result<Box<dyn Cartridge>, EmulationError> {
+    let ram_size = header.ram_size.unwrap_or(0);
+    match header.cartridge_type.mapper {
+        Mapper::RomOnly => Ok(Box::new(RomOnly::new(rom))),
+        Mapper::Mbc1 => Ok(Box::new(Mbc1::new(rom, ram_size))),
+        Mapper::Mbc3 => {
+            let rtc = if header.cartridge_type.timer {
+                Some(Rtc::new(clock))
+            } else {
+                None
+            };
+            Ok(Box::new(Mbc3::new(rom, ram_size, rtc)))
+        }
+        Mapper::Mbc2 => Ok(Box::new(Mbc2::new(rom))),
+        Mapper::Mbc5 => Ok(Box::new(Mbc5::new(rom, ram_size, header.cartridge_type.rumble))),
+        mapper => Err(EmulationError::UnsupportedCartridge { mapper }),
+    }
+}
+
+// Reads from the ROM with the 16 KiB bank already chosen by the mapper. A bank
+// past the end of the ROM wraps around like the unconnected address lines would.
+pub fn read_banked(data: &[u8], bank_size: usize, bank: usize, offset: usize) -> u8 {
+    let bank_count = std::cmp::max(data.len() / bank_size, 1);
+    data.get((bank % bank_count) * bank_size + offset)
+        .copied()
+        .unwrap_or(DISCONNECTED_VALUE)
+}
+
+pub fn write_banked(data: &mut [u8], bank_size: usize, bank: usize, offset: usize, value: u8) {
+    let bank_count = std::cmp::max(data.len() / bank_size, 1);
+    if let Some(byte) = data.get_mut((bank % bank_count) * bank_size + offset) {
+        *byte = value;
+    }
+}
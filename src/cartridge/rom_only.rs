@@ -0,0 +1,46 @@
+use crate::cartridge::{Cartridge, DISCONNECTED_VALUE, RAM_BANK_SIZE};
+
+// 32 KiB of ROM wired straight to the bus, plus an optional 8 KiB of RAM that is
+// always provided since some homebrew forgets to declare it in the header
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+}
+
+impl RomOnly {
+    pub fn new(rom: Vec<u8>) -> RomOnly {
+        RomOnly {
+            rom,
+            ram: vec![0; RAM_BANK_SIZE],
+        }
+    }
+}
+
+impl Cartridge for RomOnly {
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom
+            .get(address as usize)
+            .copied()
+            .unwrap_or(DISCONNECTED_VALUE)
+    }
+
+    fn write_rom(&mut self, _address: u16, _value: u8) {
+        // No mapper, nothing to control
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.ram[address as usize & (RAM_BANK_SIZE - 1)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        self.ram[address as usize & (RAM_BANK_SIZE - 1)] = value;
+    }
+}
@@ -0,0 +1,402 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_MINUTE: u64 = 60;
+const SECONDS_PER_HOUR: u64 = 60 * SECONDS_PER_MINUTE;
+const SECONDS_PER_DAY: u64 = 24 * SECONDS_PER_HOUR;
+// The day counter is 9 bits wide, past that the carry bit is set
+const DAY_COUNTER_LIMIT: u64 = 512;
+
+// Widths of the counters, which can be written with values past their range
+const SECONDS_MASK: u8 = 0x3F;
+const MINUTES_MASK: u8 = 0x3F;
+const HOURS_MASK: u8 = 0x1F;
+
+const DAY_HIGH_BIT: u8 = 0b0000_0001;
+const HALT_BIT: u8 = 0b0100_0000;
+const DAY_CARRY_BIT: u8 = 0b1000_0000;
+
+// The footer most emulators append to MBC3 saves: the live then latched registers
+// as five little endian u32s each, then the save time as a u64 Unix timestamp.
+// Some older emulators only wrote a 32 bit timestamp.
+pub const RTC_FOOTER_SIZE: usize = 48;
+pub const SHORT_RTC_FOOTER_SIZE: usize = 44;
+const FOOTER_TIMESTAMP_OFFSET: usize = 40;
+
+// Where the RTC gets the time from, in whole seconds since the Unix epoch
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub day_low: u8,
+    // Bit 0 is day bit 8, bit 6 halts the clock and bit 7 is the day carry
+    pub day_high: u8,
+}
+
+impl RtcRegisters {
+    fn days(&self) -> u64 {
+        ((self.day_high & DAY_HIGH_BIT) as u64) << 8 | self.day_low as u64
+    }
+
+    fn is_halted(&self) -> bool {
+        self.day_high & HALT_BIT != 0
+    }
+
+    fn advance(&mut self, mut elapsed: u64) {
+        // A counter written past its range keeps counting up to its bit width and wraps to 0
+        // without carrying, so step a second at a time until every field is back in range
+        while elapsed > 0 && !self.in_range() {
+            self.tick();
+            elapsed -= 1;
+        }
+        if elapsed == 0 {
+            return;
+        }
+
+        let total = elapsed
+            + self.seconds as u64
+            + self.minutes as u64 * SECONDS_PER_MINUTE
+            + self.hours as u64 * SECONDS_PER_HOUR;
+        self.seconds = (total % SECONDS_PER_MINUTE) as u8;
+        self.minutes = ((total / SECONDS_PER_MINUTE) % 60) as u8;
+        self.hours = ((total / SECONDS_PER_HOUR) % 24) as u8;
+        self.add_days(total / SECONDS_PER_DAY);
+    }
+
+    fn in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    fn tick(&mut self) {
+        let (seconds, carry) = step_counter(self.seconds, 60, SECONDS_MASK);
+        self.seconds = seconds;
+        if !carry {
+            return;
+        }
+        let (minutes, carry) = step_counter(self.minutes, 60, MINUTES_MASK);
+        self.minutes = minutes;
+        if !carry {
+            return;
+        }
+        let (hours, carry) = step_counter(self.hours, 24, HOURS_MASK);
+        self.hours = hours;
+        if carry {
+            self.add_days(1);
+        }
+    }
+
+    fn add_days(&mut self, elapsed: u64) {
+        let mut days = self.days() + elapsed;
+        if days >= DAY_COUNTER_LIMIT {
+            self.day_high |= DAY_CARRY_BIT;
+            days %= DAY_COUNTER_LIMIT;
+        }
+        self.day_low = (days & 0xFF) as u8;
+        self.day_high = (self.day_high & !DAY_HIGH_BIT) | (days >> 8) as u8;
+    }
+}
+
+// Counts one up, returns the new value and whether it carried into the next field. Only the
+// step from limit - 1 carries, values past the limit wrap at the bit width on their own
+fn step_counter(value: u8, limit: u8, mask: u8) -> (u8, bool) {
+    if value == limit - 1 {
+        (0, true)
+    } else {
+        (value.wrapping_add(1) & mask, false)
+    }
+}
+
+// The MBC3 real time clock. The live registers keep counting against the clock
+// source, the CPU only ever reads the copy taken by the last latch.
+pub struct Rtc {
+    clock: Box<dyn Clock>,
+    live: RtcRegisters,
+    latched: RtcRegisters,
+    // Clock time the live registers were last brought up to date at
+    last_update: u64,
+}
+
+impl Rtc {
+    pub fn new(clock: Box<dyn Clock>) -> Rtc {
+        let last_update = clock.now();
+        Rtc {
+            clock,
+            live: RtcRegisters::default(),
+            latched: RtcRegisters::default(),
+            last_update,
+        }
+    }
+
+    fn update(&mut self) {
+        let now = self.clock.now();
+        if !self.live.is_halted() {
+            self.live.advance(now.saturating_sub(self.last_update));
+        }
+        self.last_update = now;
+    }
+
+    pub fn latch(&mut self) {
+        self.update();
+        self.latched = self.live;
+    }
+
+    // Register is the RAM bank number used to select it, 0x08 to 0x0C
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.latched.seconds,
+            0x09 => self.latched.minutes,
+            0x0A => self.latched.hours,
+            0x0B => self.latched.day_low,
+            0x0C => self.latched.day_high,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.update();
+        match register {
+            0x08 => self.live.seconds = value & SECONDS_MASK,
+            0x09 => self.live.minutes = value & MINUTES_MASK,
+            0x0A => self.live.hours = value & HOURS_MASK,
+            0x0B => self.live.day_low = value,
+            0x0C => self.live.day_high = value & (DAY_HIGH_BIT | HALT_BIT | DAY_CARRY_BIT),
+            _ => {}
+        }
+    }
+
+    // The live registers as of now, for saving alongside the cartridge RAM
+    pub fn live_registers(&mut self) -> RtcRegisters {
+        self.update();
+        self.live
+    }
+
+    pub fn latched_registers(&self) -> RtcRegisters {
+        self.latched
+    }
+
+    // Restores a saved state, catching up on the time that passed since it was saved
+    pub fn restore(&mut self, live: RtcRegisters, latched: RtcRegisters, saved_at: u64) {
+        self.live = live;
+        self.latched = latched;
+        self.last_update = saved_at;
+        self.update();
+    }
+
+    pub fn footer(&mut self) -> [u8; RTC_FOOTER_SIZE] {
+        let live = self.live_registers();
+        let mut footer = [0; RTC_FOOTER_SIZE];
+        for (index, value) in registers_to_array(&live)
+            .iter()
+            .chain(registers_to_array(&self.latched).iter())
+            .enumerate()
+        {
+            footer[index * 4..index * 4 + 4].copy_from_slice(&(*value as u32).to_le_bytes());
+        }
+        footer[FOOTER_TIMESTAMP_OFFSET..].copy_from_slice(&self.last_update.to_le_bytes());
+        footer
+    }
+
+    // Takes either footer size, anything else is ignored
+    pub fn load_footer(&mut self, footer: &[u8]) {
+        let saved_at = match footer.len() {
+            RTC_FOOTER_SIZE => {
+                let mut timestamp = [0; 8];
+                timestamp.copy_from_slice(&footer[FOOTER_TIMESTAMP_OFFSET..]);
+                u64::from_le_bytes(timestamp)
+            }
+            SHORT_RTC_FOOTER_SIZE => {
+                let mut timestamp = [0; 4];
+                timestamp.copy_from_slice(&footer[FOOTER_TIMESTAMP_OFFSET..]);
+                u32::from_le_bytes(timestamp) as u64
+            }
+            _ => return,
+        };
+        // Only the low byte of each u32 carries a register
+        let register = |index: usize| footer[index * 4];
+        let live = registers_from_array([register(0), register(1), register(2), register(3), register(4)]);
+        let latched = registers_from_array([register(5), register(6), register(7), register(8), register(9)]);
+        self.restore(live, latched, saved_at);
+    }
+}
+
+fn registers_to_array(registers: &RtcRegisters) -> [u8; 5] {
+    [
+        registers.seconds,
+        registers.minutes,
+        registers.hours,
+        registers.day_low,
+        registers.day_high,
+    ]
+}
+
+fn registers_from_array(values: [u8; 5]) -> RtcRegisters {
+    RtcRegisters {
+        seconds: values[0],
+        minutes: values[1],
+        hours: values[2],
+        day_low: values[3],
+        day_high: values[4],
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // A clock the test moves forward by hand
+    #[derive(Clone, Default)]
+    pub struct ManualClock(pub Rc<Cell<u64>>);
+
+    impl ManualClock {
+        pub fn advance(&self, seconds: u64) {
+            self.0.set(self.0.get() + seconds);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_latch_snapshots_time() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+        clock.advance(SECONDS_PER_DAY + 2 * SECONDS_PER_HOUR + 3 * SECONDS_PER_MINUTE + 4);
+        assert_eq!(rtc.read(0x08), 0);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 4);
+        assert_eq!(rtc.read(0x09), 3);
+        assert_eq!(rtc.read(0x0A), 2);
+        assert_eq!(rtc.read(0x0B), 1);
+        assert_eq!(rtc.read(0x0C), 0);
+
+        clock.advance(10);
+        assert_eq!(rtc.read(0x08), 4);
+    }
+
+    #[test]
+    fn test_halt_stops_counting() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+        clock.advance(5);
+        rtc.write(0x0C, HALT_BIT);
+        clock.advance(100);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 5);
+
+        rtc.write(0x0C, 0);
+        clock.advance(1);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 6);
+    }
+
+    #[test]
+    fn test_day_counter_overflow_sets_carry() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, DAY_HIGH_BIT);
+        clock.advance(SECONDS_PER_DAY);
+        rtc.latch();
+        assert_eq!(rtc.read(0x0B), 0);
+        assert_eq!(rtc.read(0x0C), DAY_CARRY_BIT);
+
+        // The carry stays set until it is written back to 0
+        clock.advance(SECONDS_PER_DAY);
+        rtc.latch();
+        assert_eq!(rtc.read(0x0C), DAY_CARRY_BIT);
+        rtc.write(0x0C, 0);
+        rtc.latch();
+        assert_eq!(rtc.read(0x0C), 0);
+    }
+
+    #[test]
+    fn test_written_seconds_count_from_write() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+        clock.advance(30);
+        rtc.write(0x08, 58);
+        clock.advance(3);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 1);
+        assert_eq!(rtc.read(0x09), 1);
+    }
+
+    #[test]
+    fn test_out_of_range_values_wrap_without_carry() {
+        let clock = ManualClock::default();
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+        rtc.write(0x08, 60);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 60);
+        assert_eq!(rtc.read(0x09), 0);
+
+        clock.advance(4);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 0);
+        assert_eq!(rtc.read(0x09), 0);
+
+        rtc.write(0x08, 59);
+        rtc.write(0x09, 63);
+        rtc.write(0x0A, 31);
+        clock.advance(62);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 1);
+        assert_eq!(rtc.read(0x09), 1);
+        assert_eq!(rtc.read(0x0A), 31);
+        assert_eq!(rtc.read(0x0B), 0);
+
+        // Hours wrap from 31 to 0 without bumping the day
+        clock.advance(59 * SECONDS_PER_MINUTE);
+        rtc.latch();
+        assert_eq!(rtc.read(0x09), 0);
+        assert_eq!(rtc.read(0x0A), 0);
+        assert_eq!(rtc.read(0x0B), 0);
+    }
+
+    #[test]
+    fn test_footer_layout() {
+        let clock = ManualClock::default();
+        clock.advance(0x1_0000_0005);
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+        rtc.write(0x09, 7);
+        rtc.latch();
+        let footer = rtc.footer();
+        assert_eq!(&footer[4..8], &[7, 0, 0, 0]);
+        assert_eq!(&footer[24..28], &[7, 0, 0, 0]);
+        assert_eq!(&footer[40..48], &[5, 0, 0, 0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_short_footer_catches_up() {
+        let clock = ManualClock::default();
+        clock.advance(110);
+        let mut footer = [0; SHORT_RTC_FOOTER_SIZE];
+        footer[0] = 30;
+        footer[40] = 100;
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+        rtc.load_footer(&footer);
+        rtc.latch();
+        assert_eq!(rtc.read(0x08), 40);
+    }
+}
@@ -0,0 +1,11 @@
+use crate::model::Model;
+use crate::ppu::PpuBackend;
+
+// Choices fixed when the emulator is built
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Config {
+    pub model: Model,
+    pub ppu_backend: PpuBackend,
+    // Hide the earlier of two opposing d-pad directions held at once
+    pub filter_opposing_directions: bool,
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct FlagsRegister {
     pub zero: bool,
     pub subtract: bool,
@@ -37,6 +37,8 @@ impl std::convert::From<FlagsRegister> for u8 {
     }
 }
 
+// Only the upper nibble of F exists in hardware, so the low four bits of the
+// byte are always dropped. POP AF relies on this to keep them reading as zero.
 impl std::convert::From<u8> for FlagsRegister {
     fn from(byte: u8) -> Self {
         let zero = ((byte >> ZERO_FLAG_BYTE_POSITION) & 0b1) != 0;
@@ -52,3 +54,25 @@ impl std::convert::From<u8> for FlagsRegister {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let flags = FlagsRegister::from(0b1010_0000u8);
+        assert!(flags.zero);
+        assert!(!flags.subtract);
+        assert!(flags.half_carry);
+        assert!(!flags.carry);
+        assert_eq!(u8::from(flags), 0b1010_0000);
+    }
+
+    #[test]
+    fn test_low_nibble_is_always_zero() {
+        for byte in 0..=0xffu8 {
+            assert_eq!(u8::from(FlagsRegister::from(byte)), byte & 0xf0);
+        }
+    }
+}
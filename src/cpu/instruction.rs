@@ -1,6 +1,10 @@
 #[derive(Debug)]
 pub enum Instruction {
     NOP,
+    HALT,
+    STOP,
+    DI,
+    EI,
     ADD(ArithmeticTarget),
     ADC(ArithmeticTarget),
     SUB(ArithmeticTarget),
@@ -9,8 +13,41 @@ pub enum Instruction {
     OR(ArithmeticTarget),
     XOR(ArithmeticTarget),
     CP(ArithmeticTarget),
+    ADDHL(ADDHLTarget),
+    ADDSP,
+    INC(IncDecTarget),
+    DEC(IncDecTarget),
+    DAA,
+    CPL,
+    SCF,
+    CCF,
+    RLCA,
+    RRCA,
+    RLA,
+    RRA,
     LD(LoadType),
-    BIT(BitPosition),
+    JP(JumpTest),
+    JPHL,
+    JR(JumpTest),
+    CALL(JumpTest),
+    RET(JumpTest),
+    RETI,
+    RST(RstVector),
+    PUSH(StackTarget),
+    POP(StackTarget),
+    RLC(PrefixTarget),
+    RRC(PrefixTarget),
+    RL(PrefixTarget),
+    RR(PrefixTarget),
+    SLA(PrefixTarget),
+    SRA(PrefixTarget),
+    SWAP(PrefixTarget),
+    SRL(PrefixTarget),
+    BIT(BitPosition, PrefixTarget),
+    RES(BitPosition, PrefixTarget),
+    SET(BitPosition, PrefixTarget),
+    // One of the 11 opcodes with no instruction behind it, real hardware locks up on these
+    ILLEGAL(u8),
 }
 
 #[derive(Debug)]
@@ -23,6 +60,31 @@ pub enum ArithmeticTarget {
     H,
     L,
     HLI,
+    D8,
+}
+
+#[derive(Debug)]
+pub enum ADDHLTarget {
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
+#[derive(Debug)]
+pub enum IncDecTarget {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    HLI,
+    BC,
+    DE,
+    HL,
+    SP,
 }
 
 #[derive(Debug)]
@@ -53,6 +115,10 @@ pub enum LoadType {
     Byte(LoadByteTarget, LoadByteSource),
     Word(LoadWordTarget),
     IndirectFromA(Indirect),
+    AFromIndirect(Indirect),
+    IndirectFromSP,
+    SPFromHL,
+    HLFromSPN,
 }
 
 #[derive(Debug)]
@@ -70,9 +136,66 @@ pub enum Indirect {
     HLIndirectMinus,
     HLIndirectPlus,
     WordIndirect,
+    ByteIndirect,
     LastByteIndirect,
 }
 
+#[derive(Debug)]
+pub enum JumpTest {
+    NotZero,
+    Zero,
+    NotCarry,
+    Carry,
+    Always,
+}
+
+#[derive(Debug)]
+pub enum StackTarget {
+    AF,
+    BC,
+    DE,
+    HL,
+}
+
+#[derive(Debug)]
+pub enum RstVector {
+    H00,
+    H08,
+    H10,
+    H18,
+    H20,
+    H28,
+    H30,
+    H38,
+}
+
+impl std::convert::From<RstVector> for u16 {
+    fn from(vector: RstVector) -> u16 {
+        match vector {
+            RstVector::H00 => 0x00,
+            RstVector::H08 => 0x08,
+            RstVector::H10 => 0x10,
+            RstVector::H18 => 0x18,
+            RstVector::H20 => 0x20,
+            RstVector::H28 => 0x28,
+            RstVector::H30 => 0x30,
+            RstVector::H38 => 0x38,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PrefixTarget {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    HLI,
+}
+
 #[derive(Debug)]
 pub enum BitPosition {
     B0,
@@ -85,6 +208,21 @@ pub enum BitPosition {
     B7,
 }
 
+impl std::convert::From<BitPosition> for u8 {
+    fn from(position: BitPosition) -> u8 {
+        match position {
+            BitPosition::B0 => 0,
+            BitPosition::B1 => 1,
+            BitPosition::B2 => 2,
+            BitPosition::B3 => 3,
+            BitPosition::B4 => 4,
+            BitPosition::B5 => 5,
+            BitPosition::B6 => 6,
+            BitPosition::B7 => 7,
+        }
+    }
+}
+
 impl Instruction {
     pub fn from_byte(byte: u8, prefixed: bool) -> Option<Instruction> {
         if prefixed {
@@ -96,12 +234,262 @@ impl Instruction {
 
     fn from_byte_prefixed(byte: u8) -> Option<Instruction> {
         match byte {
-            0x7c => Some(Instruction::BIT(BitPosition::B7)),
-            _ =>
-            /* TODO: Add mapping for rest of instructions */
-            {
-                None
-            }
+            0x00 => Some(Instruction::RLC(PrefixTarget::B)),
+            0x01 => Some(Instruction::RLC(PrefixTarget::C)),
+            0x02 => Some(Instruction::RLC(PrefixTarget::D)),
+            0x03 => Some(Instruction::RLC(PrefixTarget::E)),
+            0x04 => Some(Instruction::RLC(PrefixTarget::H)),
+            0x05 => Some(Instruction::RLC(PrefixTarget::L)),
+            0x06 => Some(Instruction::RLC(PrefixTarget::HLI)),
+            0x07 => Some(Instruction::RLC(PrefixTarget::A)),
+            0x08 => Some(Instruction::RRC(PrefixTarget::B)),
+            0x09 => Some(Instruction::RRC(PrefixTarget::C)),
+            0x0a => Some(Instruction::RRC(PrefixTarget::D)),
+            0x0b => Some(Instruction::RRC(PrefixTarget::E)),
+            0x0c => Some(Instruction::RRC(PrefixTarget::H)),
+            0x0d => Some(Instruction::RRC(PrefixTarget::L)),
+            0x0e => Some(Instruction::RRC(PrefixTarget::HLI)),
+            0x0f => Some(Instruction::RRC(PrefixTarget::A)),
+            0x10 => Some(Instruction::RL(PrefixTarget::B)),
+            0x11 => Some(Instruction::RL(PrefixTarget::C)),
+            0x12 => Some(Instruction::RL(PrefixTarget::D)),
+            0x13 => Some(Instruction::RL(PrefixTarget::E)),
+            0x14 => Some(Instruction::RL(PrefixTarget::H)),
+            0x15 => Some(Instruction::RL(PrefixTarget::L)),
+            0x16 => Some(Instruction::RL(PrefixTarget::HLI)),
+            0x17 => Some(Instruction::RL(PrefixTarget::A)),
+            0x18 => Some(Instruction::RR(PrefixTarget::B)),
+            0x19 => Some(Instruction::RR(PrefixTarget::C)),
+            0x1a => Some(Instruction::RR(PrefixTarget::D)),
+            0x1b => Some(Instruction::RR(PrefixTarget::E)),
+            0x1c => Some(Instruction::RR(PrefixTarget::H)),
+            0x1d => Some(Instruction::RR(PrefixTarget::L)),
+            0x1e => Some(Instruction::RR(PrefixTarget::HLI)),
+            0x1f => Some(Instruction::RR(PrefixTarget::A)),
+            0x20 => Some(Instruction::SLA(PrefixTarget::B)),
+            0x21 => Some(Instruction::SLA(PrefixTarget::C)),
+            0x22 => Some(Instruction::SLA(PrefixTarget::D)),
+            0x23 => Some(Instruction::SLA(PrefixTarget::E)),
+            0x24 => Some(Instruction::SLA(PrefixTarget::H)),
+            0x25 => Some(Instruction::SLA(PrefixTarget::L)),
+            0x26 => Some(Instruction::SLA(PrefixTarget::HLI)),
+            0x27 => Some(Instruction::SLA(PrefixTarget::A)),
+            0x28 => Some(Instruction::SRA(PrefixTarget::B)),
+            0x29 => Some(Instruction::SRA(PrefixTarget::C)),
+            0x2a => Some(Instruction::SRA(PrefixTarget::D)),
+            0x2b => Some(Instruction::SRA(PrefixTarget::E)),
+            0x2c => Some(Instruction::SRA(PrefixTarget::H)),
+            0x2d => Some(Instruction::SRA(PrefixTarget::L)),
+            0x2e => Some(Instruction::SRA(PrefixTarget::HLI)),
+            0x2f => Some(Instruction::SRA(PrefixTarget::A)),
+            0x30 => Some(Instruction::SWAP(PrefixTarget::B)),
+            0x31 => Some(Instruction::SWAP(PrefixTarget::C)),
+            0x32 => Some(Instruction::SWAP(PrefixTarget::D)),
+            0x33 => Some(Instruction::SWAP(PrefixTarget::E)),
+            0x34 => Some(Instruction::SWAP(PrefixTarget::H)),
+            0x35 => Some(Instruction::SWAP(PrefixTarget::L)),
+            0x36 => Some(Instruction::SWAP(PrefixTarget::HLI)),
+            0x37 => Some(Instruction::SWAP(PrefixTarget::A)),
+            0x38 => Some(Instruction::SRL(PrefixTarget::B)),
+            0x39 => Some(Instruction::SRL(PrefixTarget::C)),
+            0x3a => Some(Instruction::SRL(PrefixTarget::D)),
+            0x3b => Some(Instruction::SRL(PrefixTarget::E)),
+            0x3c => Some(Instruction::SRL(PrefixTarget::H)),
+            0x3d => Some(Instruction::SRL(PrefixTarget::L)),
+            0x3e => Some(Instruction::SRL(PrefixTarget::HLI)),
+            0x3f => Some(Instruction::SRL(PrefixTarget::A)),
+            0x40 => Some(Instruction::BIT(BitPosition::B0, PrefixTarget::B)),
+            0x41 => Some(Instruction::BIT(BitPosition::B0, PrefixTarget::C)),
+            0x42 => Some(Instruction::BIT(BitPosition::B0, PrefixTarget::D)),
+            0x43 => Some(Instruction::BIT(BitPosition::B0, PrefixTarget::E)),
+            0x44 => Some(Instruction::BIT(BitPosition::B0, PrefixTarget::H)),
+            0x45 => Some(Instruction::BIT(BitPosition::B0, PrefixTarget::L)),
+            0x46 => Some(Instruction::BIT(BitPosition::B0, PrefixTarget::HLI)),
+            0x47 => Some(Instruction::BIT(BitPosition::B0, PrefixTarget::A)),
+            0x48 => Some(Instruction::BIT(BitPosition::B1, PrefixTarget::B)),
+            0x49 => Some(Instruction::BIT(BitPosition::B1, PrefixTarget::C)),
+            0x4a => Some(Instruction::BIT(BitPosition::B1, PrefixTarget::D)),
+            0x4b => Some(Instruction::BIT(BitPosition::B1, PrefixTarget::E)),
+            0x4c => Some(Instruction::BIT(BitPosition::B1, PrefixTarget::H)),
+            0x4d => Some(Instruction::BIT(BitPosition::B1, PrefixTarget::L)),
+            0x4e => Some(Instruction::BIT(BitPosition::B1, PrefixTarget::HLI)),
+            0x4f => Some(Instruction::BIT(BitPosition::B1, PrefixTarget::A)),
+            0x50 => Some(Instruction::BIT(BitPosition::B2, PrefixTarget::B)),
+            0x51 => Some(Instruction::BIT(BitPosition::B2, PrefixTarget::C)),
+            0x52 => Some(Instruction::BIT(BitPosition::B2, PrefixTarget::D)),
+            0x53 => Some(Instruction::BIT(BitPosition::B2, PrefixTarget::E)),
+            0x54 => Some(Instruction::BIT(BitPosition::B2, PrefixTarget::H)),
+            0x55 => Some(Instruction::BIT(BitPosition::B2, PrefixTarget::L)),
+            0x56 => Some(Instruction::BIT(BitPosition::B2, PrefixTarget::HLI)),
+            0x57 => Some(Instruction::BIT(BitPosition::B2, PrefixTarget::A)),
+            0x58 => Some(Instruction::BIT(BitPosition::B3, PrefixTarget::B)),
+            0x59 => Some(Instruction::BIT(BitPosition::B3, PrefixTarget::C)),
+            0x5a => Some(Instruction::BIT(BitPosition::B3, PrefixTarget::D)),
+            0x5b => Some(Instruction::BIT(BitPosition::B3, PrefixTarget::E)),
+            0x5c => Some(Instruction::BIT(BitPosition::B3, PrefixTarget::H)),
+            0x5d => Some(Instruction::BIT(BitPosition::B3, PrefixTarget::L)),
+            0x5e => Some(Instruction::BIT(BitPosition::B3, PrefixTarget::HLI)),
+            0x5f => Some(Instruction::BIT(BitPosition::B3, PrefixTarget::A)),
+            0x60 => Some(Instruction::BIT(BitPosition::B4, PrefixTarget::B)),
+            0x61 => Some(Instruction::BIT(BitPosition::B4, PrefixTarget::C)),
+            0x62 => Some(Instruction::BIT(BitPosition::B4, PrefixTarget::D)),
+            0x63 => Some(Instruction::BIT(BitPosition::B4, PrefixTarget::E)),
+            0x64 => Some(Instruction::BIT(BitPosition::B4, PrefixTarget::H)),
+            0x65 => Some(Instruction::BIT(BitPosition::B4, PrefixTarget::L)),
+            0x66 => Some(Instruction::BIT(BitPosition::B4, PrefixTarget::HLI)),
+            0x67 => Some(Instruction::BIT(BitPosition::B4, PrefixTarget::A)),
+            0x68 => Some(Instruction::BIT(BitPosition::B5, PrefixTarget::B)),
+            0x69 => Some(Instruction::BIT(BitPosition::B5, PrefixTarget::C)),
+            0x6a => Some(Instruction::BIT(BitPosition::B5, PrefixTarget::D)),
+            0x6b => Some(Instruction::BIT(BitPosition::B5, PrefixTarget::E)),
+            0x6c => Some(Instruction::BIT(BitPosition::B5, PrefixTarget::H)),
+            0x6d => Some(Instruction::BIT(BitPosition::B5, PrefixTarget::L)),
+            0x6e => Some(Instruction::BIT(BitPosition::B5, PrefixTarget::HLI)),
+            0x6f => Some(Instruction::BIT(BitPosition::B5, PrefixTarget::A)),
+            0x70 => Some(Instruction::BIT(BitPosition::B6, PrefixTarget::B)),
+            0x71 => Some(Instruction::BIT(BitPosition::B6, PrefixTarget::C)),
+            0x72 => Some(Instruction::BIT(BitPosition::B6, PrefixTarget::D)),
+            0x73 => Some(Instruction::BIT(BitPosition::B6, PrefixTarget::E)),
+            0x74 => Some(Instruction::BIT(BitPosition::B6, PrefixTarget::H)),
+            0x75 => Some(Instruction::BIT(BitPosition::B6, PrefixTarget::L)),
+            0x76 => Some(Instruction::BIT(BitPosition::B6, PrefixTarget::HLI)),
+            0x77 => Some(Instruction::BIT(BitPosition::B6, PrefixTarget::A)),
+            0x78 => Some(Instruction::BIT(BitPosition::B7, PrefixTarget::B)),
+            0x79 => Some(Instruction::BIT(BitPosition::B7, PrefixTarget::C)),
+            0x7a => Some(Instruction::BIT(BitPosition::B7, PrefixTarget::D)),
+            0x7b => Some(Instruction::BIT(BitPosition::B7, PrefixTarget::E)),
+            0x7c => Some(Instruction::BIT(BitPosition::B7, PrefixTarget::H)),
+            0x7d => Some(Instruction::BIT(BitPosition::B7, PrefixTarget::L)),
+            0x7e => Some(Instruction::BIT(BitPosition::B7, PrefixTarget::HLI)),
+            0x7f => Some(Instruction::BIT(BitPosition::B7, PrefixTarget::A)),
+            0x80 => Some(Instruction::RES(BitPosition::B0, PrefixTarget::B)),
+            0x81 => Some(Instruction::RES(BitPosition::B0, PrefixTarget::C)),
+            0x82 => Some(Instruction::RES(BitPosition::B0, PrefixTarget::D)),
+            0x83 => Some(Instruction::RES(BitPosition::B0, PrefixTarget::E)),
+            0x84 => Some(Instruction::RES(BitPosition::B0, PrefixTarget::H)),
+            0x85 => Some(Instruction::RES(BitPosition::B0, PrefixTarget::L)),
+            0x86 => Some(Instruction::RES(BitPosition::B0, PrefixTarget::HLI)),
+            0x87 => Some(Instruction::RES(BitPosition::B0, PrefixTarget::A)),
+            0x88 => Some(Instruction::RES(BitPosition::B1, PrefixTarget::B)),
+            0x89 => Some(Instruction::RES(BitPosition::B1, PrefixTarget::C)),
+            0x8a => Some(Instruction::RES(BitPosition::B1, PrefixTarget::D)),
+            0x8b => Some(Instruction::RES(BitPosition::B1, PrefixTarget::E)),
+            0x8c => Some(Instruction::RES(BitPosition::B1, PrefixTarget::H)),
+            0x8d => Some(Instruction::RES(BitPosition::B1, PrefixTarget::L)),
+            0x8e => Some(Instruction::RES(BitPosition::B1, PrefixTarget::HLI)),
+            0x8f => Some(Instruction::RES(BitPosition::B1, PrefixTarget::A)),
+            0x90 => Some(Instruction::RES(BitPosition::B2, PrefixTarget::B)),
+            0x91 => Some(Instruction::RES(BitPosition::B2, PrefixTarget::C)),
+            0x92 => Some(Instruction::RES(BitPosition::B2, PrefixTarget::D)),
+            0x93 => Some(Instruction::RES(BitPosition::B2, PrefixTarget::E)),
+            0x94 => Some(Instruction::RES(BitPosition::B2, PrefixTarget::H)),
+            0x95 => Some(Instruction::RES(BitPosition::B2, PrefixTarget::L)),
+            0x96 => Some(Instruction::RES(BitPosition::B2, PrefixTarget::HLI)),
+            0x97 => Some(Instruction::RES(BitPosition::B2, PrefixTarget::A)),
+            0x98 => Some(Instruction::RES(BitPosition::B3, PrefixTarget::B)),
+            0x99 => Some(Instruction::RES(BitPosition::B3, PrefixTarget::C)),
+            0x9a => Some(Instruction::RES(BitPosition::B3, PrefixTarget::D)),
+            0x9b => Some(Instruction::RES(BitPosition::B3, PrefixTarget::E)),
+            0x9c => Some(Instruction::RES(BitPosition::B3, PrefixTarget::H)),
+            0x9d => Some(Instruction::RES(BitPosition::B3, PrefixTarget::L)),
+            0x9e => Some(Instruction::RES(BitPosition::B3, PrefixTarget::HLI)),
+            0x9f => Some(Instruction::RES(BitPosition::B3, PrefixTarget::A)),
+            0xa0 => Some(Instruction::RES(BitPosition::B4, PrefixTarget::B)),
+            0xa1 => Some(Instruction::RES(BitPosition::B4, PrefixTarget::C)),
+            0xa2 => Some(Instruction::RES(BitPosition::B4, PrefixTarget::D)),
+            0xa3 => Some(Instruction::RES(BitPosition::B4, PrefixTarget::E)),
+            0xa4 => Some(Instruction::RES(BitPosition::B4, PrefixTarget::H)),
+            0xa5 => Some(Instruction::RES(BitPosition::B4, PrefixTarget::L)),
+            0xa6 => Some(Instruction::RES(BitPosition::B4, PrefixTarget::HLI)),
+            0xa7 => Some(Instruction::RES(BitPosition::B4, PrefixTarget::A)),
+            0xa8 => Some(Instruction::RES(BitPosition::B5, PrefixTarget::B)),
+            0xa9 => Some(Instruction::RES(BitPosition::B5, PrefixTarget::C)),
+            0xaa => Some(Instruction::RES(BitPosition::B5, PrefixTarget::D)),
+            0xab => Some(Instruction::RES(BitPosition::B5, PrefixTarget::E)),
+            0xac => Some(Instruction::RES(BitPosition::B5, PrefixTarget::H)),
+            0xad => Some(Instruction::RES(BitPosition::B5, PrefixTarget::L)),
+            0xae => Some(Instruction::RES(BitPosition::B5, PrefixTarget::HLI)),
+            0xaf => Some(Instruction::RES(BitPosition::B5, PrefixTarget::A)),
+            0xb0 => Some(Instruction::RES(BitPosition::B6, PrefixTarget::B)),
+            0xb1 => Some(Instruction::RES(BitPosition::B6, PrefixTarget::C)),
+            0xb2 => Some(Instruction::RES(BitPosition::B6, PrefixTarget::D)),
+            0xb3 => Some(Instruction::RES(BitPosition::B6, PrefixTarget::E)),
+            0xb4 => Some(Instruction::RES(BitPosition::B6, PrefixTarget::H)),
+            0xb5 => Some(Instruction::RES(BitPosition::B6, PrefixTarget::L)),
+            0xb6 => Some(Instruction::RES(BitPosition::B6, PrefixTarget::HLI)),
+            0xb7 => Some(Instruction::RES(BitPosition::B6, PrefixTarget::A)),
+            0xb8 => Some(Instruction::RES(BitPosition::B7, PrefixTarget::B)),
+            0xb9 => Some(Instruction::RES(BitPosition::B7, PrefixTarget::C)),
+            0xba => Some(Instruction::RES(BitPosition::B7, PrefixTarget::D)),
+            0xbb => Some(Instruction::RES(BitPosition::B7, PrefixTarget::E)),
+            0xbc => Some(Instruction::RES(BitPosition::B7, PrefixTarget::H)),
+            0xbd => Some(Instruction::RES(BitPosition::B7, PrefixTarget::L)),
+            0xbe => Some(Instruction::RES(BitPosition::B7, PrefixTarget::HLI)),
+            0xbf => Some(Instruction::RES(BitPosition::B7, PrefixTarget::A)),
+            0xc0 => Some(Instruction::SET(BitPosition::B0, PrefixTarget::B)),
+            0xc1 => Some(Instruction::SET(BitPosition::B0, PrefixTarget::C)),
+            0xc2 => Some(Instruction::SET(BitPosition::B0, PrefixTarget::D)),
+            0xc3 => Some(Instruction::SET(BitPosition::B0, PrefixTarget::E)),
+            0xc4 => Some(Instruction::SET(BitPosition::B0, PrefixTarget::H)),
+            0xc5 => Some(Instruction::SET(BitPosition::B0, PrefixTarget::L)),
+            0xc6 => Some(Instruction::SET(BitPosition::B0, PrefixTarget::HLI)),
+            0xc7 => Some(Instruction::SET(BitPosition::B0, PrefixTarget::A)),
+            0xc8 => Some(Instruction::SET(BitPosition::B1, PrefixTarget::B)),
+            0xc9 => Some(Instruction::SET(BitPosition::B1, PrefixTarget::C)),
+            0xca => Some(Instruction::SET(BitPosition::B1, PrefixTarget::D)),
+            0xcb => Some(Instruction::SET(BitPosition::B1, PrefixTarget::E)),
+            0xcc => Some(Instruction::SET(BitPosition::B1, PrefixTarget::H)),
+            0xcd => Some(Instruction::SET(BitPosition::B1, PrefixTarget::L)),
+            0xce => Some(Instruction::SET(BitPosition::B1, PrefixTarget::HLI)),
+            0xcf => Some(Instruction::SET(BitPosition::B1, PrefixTarget::A)),
+            0xd0 => Some(Instruction::SET(BitPosition::B2, PrefixTarget::B)),
+            0xd1 => Some(Instruction::SET(BitPosition::B2, PrefixTarget::C)),
+            0xd2 => Some(Instruction::SET(BitPosition::B2, PrefixTarget::D)),
+            0xd3 => Some(Instruction::SET(BitPosition::B2, PrefixTarget::E)),
+            0xd4 => Some(Instruction::SET(BitPosition::B2, PrefixTarget::H)),
+            0xd5 => Some(Instruction::SET(BitPosition::B2, PrefixTarget::L)),
+            0xd6 => Some(Instruction::SET(BitPosition::B2, PrefixTarget::HLI)),
+            0xd7 => Some(Instruction::SET(BitPosition::B2, PrefixTarget::A)),
+            0xd8 => Some(Instruction::SET(BitPosition::B3, PrefixTarget::B)),
+            0xd9 => Some(Instruction::SET(BitPosition::B3, PrefixTarget::C)),
+            0xda => Some(Instruction::SET(BitPosition::B3, PrefixTarget::D)),
+            0xdb => Some(Instruction::SET(BitPosition::B3, PrefixTarget::E)),
+            0xdc => Some(Instruction::SET(BitPosition::B3, PrefixTarget::H)),
+            0xdd => Some(Instruction::SET(BitPosition::B3, PrefixTarget::L)),
+            0xde => Some(Instruction::SET(BitPosition::B3, PrefixTarget::HLI)),
+            0xdf => Some(Instruction::SET(BitPosition::B3, PrefixTarget::A)),
+            0xe0 => Some(Instruction::SET(BitPosition::B4, PrefixTarget::B)),
+            0xe1 => Some(Instruction::SET(BitPosition::B4, PrefixTarget::C)),
+            0xe2 => Some(Instruction::SET(BitPosition::B4, PrefixTarget::D)),
+            0xe3 => Some(Instruction::SET(BitPosition::B4, PrefixTarget::E)),
+            0xe4 => Some(Instruction::SET(BitPosition::B4, PrefixTarget::H)),
+            0xe5 => Some(Instruction::SET(BitPosition::B4, PrefixTarget::L)),
+            0xe6 => Some(Instruction::SET(BitPosition::B4, PrefixTarget::HLI)),
+            0xe7 => Some(Instruction::SET(BitPosition::B4, PrefixTarget::A)),
+            0xe8 => Some(Instruction::SET(BitPosition::B5, PrefixTarget::B)),
+            0xe9 => Some(Instruction::SET(BitPosition::B5, PrefixTarget::C)),
+            0xea => Some(Instruction::SET(BitPosition::B5, PrefixTarget::D)),
+            0xeb => Some(Instruction::SET(BitPosition::B5, PrefixTarget::E)),
+            0xec => Some(Instruction::SET(BitPosition::B5, PrefixTarget::H)),
+            0xed => Some(Instruction::SET(BitPosition::B5, PrefixTarget::L)),
+            0xee => Some(Instruction::SET(BitPosition::B5, PrefixTarget::HLI)),
+            0xef => Some(Instruction::SET(BitPosition::B5, PrefixTarget::A)),
+            0xf0 => Some(Instruction::SET(BitPosition::B6, PrefixTarget::B)),
+            0xf1 => Some(Instruction::SET(BitPosition::B6, PrefixTarget::C)),
+            0xf2 => Some(Instruction::SET(BitPosition::B6, PrefixTarget::D)),
+            0xf3 => Some(Instruction::SET(BitPosition::B6, PrefixTarget::E)),
+            0xf4 => Some(Instruction::SET(BitPosition::B6, PrefixTarget::H)),
+            0xf5 => Some(Instruction::SET(BitPosition::B6, PrefixTarget::L)),
+            0xf6 => Some(Instruction::SET(BitPosition::B6, PrefixTarget::HLI)),
+            0xf7 => Some(Instruction::SET(BitPosition::B6, PrefixTarget::A)),
+            0xf8 => Some(Instruction::SET(BitPosition::B7, PrefixTarget::B)),
+            0xf9 => Some(Instruction::SET(BitPosition::B7, PrefixTarget::C)),
+            0xfa => Some(Instruction::SET(BitPosition::B7, PrefixTarget::D)),
+            0xfb => Some(Instruction::SET(BitPosition::B7, PrefixTarget::E)),
+            0xfc => Some(Instruction::SET(BitPosition::B7, PrefixTarget::H)),
+            0xfd => Some(Instruction::SET(BitPosition::B7, PrefixTarget::L)),
+            0xfe => Some(Instruction::SET(BitPosition::B7, PrefixTarget::HLI)),
+            0xff => Some(Instruction::SET(BitPosition::B7, PrefixTarget::A)),
         }
     }
 
@@ -110,9 +498,131 @@ impl Instruction {
             0x00 => Some(Instruction::NOP),
             0x01 => Some(Instruction::LD(LoadType::Word(LoadWordTarget::BC))),
             0x02 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::BCIndirect))),
-            0x31 => Some(Instruction::LD(LoadType::Word(LoadWordTarget::SP))),
+            0x03 => Some(Instruction::INC(IncDecTarget::BC)),
+            0x04 => Some(Instruction::INC(IncDecTarget::B)),
+            0x05 => Some(Instruction::DEC(IncDecTarget::B)),
+            0x06 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::D8))),
+            0x07 => Some(Instruction::RLCA),
+            0x08 => Some(Instruction::LD(LoadType::IndirectFromSP)),
+            0x09 => Some(Instruction::ADDHL(ADDHLTarget::BC)),
+            0x0a => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::BCIndirect))),
+            0x0b => Some(Instruction::DEC(IncDecTarget::BC)),
+            0x0c => Some(Instruction::INC(IncDecTarget::C)),
+            0x0d => Some(Instruction::DEC(IncDecTarget::C)),
+            0x0e => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::D8))),
+            0x0f => Some(Instruction::RRCA),
+            0x10 => Some(Instruction::STOP),
+            0x11 => Some(Instruction::LD(LoadType::Word(LoadWordTarget::DE))),
+            0x12 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::DEIndirect))),
+            0x13 => Some(Instruction::INC(IncDecTarget::DE)),
+            0x14 => Some(Instruction::INC(IncDecTarget::D)),
+            0x15 => Some(Instruction::DEC(IncDecTarget::D)),
+            0x16 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::D8))),
+            0x17 => Some(Instruction::RLA),
+            0x18 => Some(Instruction::JR(JumpTest::Always)),
+            0x19 => Some(Instruction::ADDHL(ADDHLTarget::DE)),
+            0x1a => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::DEIndirect))),
+            0x1b => Some(Instruction::DEC(IncDecTarget::DE)),
+            0x1c => Some(Instruction::INC(IncDecTarget::E)),
+            0x1d => Some(Instruction::DEC(IncDecTarget::E)),
+            0x1e => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::D8))),
+            0x1f => Some(Instruction::RRA),
+            0x20 => Some(Instruction::JR(JumpTest::NotZero)),
             0x21 => Some(Instruction::LD(LoadType::Word(LoadWordTarget::HL))),
+            0x22 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::HLIndirectPlus))),
+            0x23 => Some(Instruction::INC(IncDecTarget::HL)),
+            0x24 => Some(Instruction::INC(IncDecTarget::H)),
+            0x25 => Some(Instruction::DEC(IncDecTarget::H)),
+            0x26 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::D8))),
+            0x27 => Some(Instruction::DAA),
+            0x28 => Some(Instruction::JR(JumpTest::Zero)),
+            0x29 => Some(Instruction::ADDHL(ADDHLTarget::HL)),
+            0x2a => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::HLIndirectPlus))),
+            0x2b => Some(Instruction::DEC(IncDecTarget::HL)),
+            0x2c => Some(Instruction::INC(IncDecTarget::L)),
+            0x2d => Some(Instruction::DEC(IncDecTarget::L)),
+            0x2e => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::D8))),
+            0x2f => Some(Instruction::CPL),
+            0x30 => Some(Instruction::JR(JumpTest::NotCarry)),
+            0x31 => Some(Instruction::LD(LoadType::Word(LoadWordTarget::SP))),
             0x32 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::HLIndirectMinus))),
+            0x33 => Some(Instruction::INC(IncDecTarget::SP)),
+            0x34 => Some(Instruction::INC(IncDecTarget::HLI)),
+            0x35 => Some(Instruction::DEC(IncDecTarget::HLI)),
+            0x36 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::D8))),
+            0x37 => Some(Instruction::SCF),
+            0x38 => Some(Instruction::JR(JumpTest::Carry)),
+            0x39 => Some(Instruction::ADDHL(ADDHLTarget::SP)),
+            0x3a => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::HLIndirectMinus))),
+            0x3b => Some(Instruction::DEC(IncDecTarget::SP)),
+            0x3c => Some(Instruction::INC(IncDecTarget::A)),
+            0x3d => Some(Instruction::DEC(IncDecTarget::A)),
+            0x3e => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::D8))),
+            0x3f => Some(Instruction::CCF),
+            0x40 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::B))),
+            0x41 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::C))),
+            0x42 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::D))),
+            0x43 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::E))),
+            0x44 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::H))),
+            0x45 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::L))),
+            0x46 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::HLI))),
+            0x47 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::B, LoadByteSource::A))),
+            0x48 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::B))),
+            0x49 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::C))),
+            0x4a => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::D))),
+            0x4b => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::E))),
+            0x4c => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::H))),
+            0x4d => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::L))),
+            0x4e => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::HLI))),
+            0x4f => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::C, LoadByteSource::A))),
+            0x50 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::B))),
+            0x51 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::C))),
+            0x52 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::D))),
+            0x53 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::E))),
+            0x54 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::H))),
+            0x55 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::L))),
+            0x56 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::HLI))),
+            0x57 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::D, LoadByteSource::A))),
+            0x58 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::B))),
+            0x59 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::C))),
+            0x5a => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::D))),
+            0x5b => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::E))),
+            0x5c => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::H))),
+            0x5d => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::L))),
+            0x5e => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::HLI))),
+            0x5f => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::E, LoadByteSource::A))),
+            0x60 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::B))),
+            0x61 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::C))),
+            0x62 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::D))),
+            0x63 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::E))),
+            0x64 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::H))),
+            0x65 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::L))),
+            0x66 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::HLI))),
+            0x67 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::H, LoadByteSource::A))),
+            0x68 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::B))),
+            0x69 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::C))),
+            0x6a => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::D))),
+            0x6b => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::E))),
+            0x6c => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::H))),
+            0x6d => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::L))),
+            0x6e => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::HLI))),
+            0x6f => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::L, LoadByteSource::A))),
+            0x70 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::B))),
+            0x71 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::C))),
+            0x72 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::D))),
+            0x73 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::E))),
+            0x74 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::H))),
+            0x75 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::L))),
+            0x76 => Some(Instruction::HALT),
+            0x77 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::HLI, LoadByteSource::A))),
+            0x78 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::B))),
+            0x79 => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::C))),
+            0x7a => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::D))),
+            0x7b => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::E))),
+            0x7c => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::H))),
+            0x7d => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::L))),
+            0x7e => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::HLI))),
+            0x7f => Some(Instruction::LD(LoadType::Byte(LoadByteTarget::A, LoadByteSource::A))),
             0x80 => Some(Instruction::ADD(ArithmeticTarget::B)),
             0x81 => Some(Instruction::ADD(ArithmeticTarget::C)),
             0x82 => Some(Instruction::ADD(ArithmeticTarget::D)),
@@ -177,11 +687,121 @@ impl Instruction {
             0xbd => Some(Instruction::CP(ArithmeticTarget::L)),
             0xbe => Some(Instruction::CP(ArithmeticTarget::HLI)),
             0xbf => Some(Instruction::CP(ArithmeticTarget::A)),
-            _ =>
-            /* TODO: Add mapping for rest of instructions */
-            {
-                None
+            0xc0 => Some(Instruction::RET(JumpTest::NotZero)),
+            0xc1 => Some(Instruction::POP(StackTarget::BC)),
+            0xc2 => Some(Instruction::JP(JumpTest::NotZero)),
+            0xc3 => Some(Instruction::JP(JumpTest::Always)),
+            0xc4 => Some(Instruction::CALL(JumpTest::NotZero)),
+            0xc5 => Some(Instruction::PUSH(StackTarget::BC)),
+            0xc6 => Some(Instruction::ADD(ArithmeticTarget::D8)),
+            0xc7 => Some(Instruction::RST(RstVector::H00)),
+            0xc8 => Some(Instruction::RET(JumpTest::Zero)),
+            0xc9 => Some(Instruction::RET(JumpTest::Always)),
+            0xca => Some(Instruction::JP(JumpTest::Zero)),
+            0xcc => Some(Instruction::CALL(JumpTest::Zero)),
+            0xcd => Some(Instruction::CALL(JumpTest::Always)),
+            0xce => Some(Instruction::ADC(ArithmeticTarget::D8)),
+            0xcf => Some(Instruction::RST(RstVector::H08)),
+            0xd0 => Some(Instruction::RET(JumpTest::NotCarry)),
+            0xd1 => Some(Instruction::POP(StackTarget::DE)),
+            0xd2 => Some(Instruction::JP(JumpTest::NotCarry)),
+            0xd4 => Some(Instruction::CALL(JumpTest::NotCarry)),
+            0xd5 => Some(Instruction::PUSH(StackTarget::DE)),
+            0xd6 => Some(Instruction::SUB(ArithmeticTarget::D8)),
+            0xd7 => Some(Instruction::RST(RstVector::H10)),
+            0xd8 => Some(Instruction::RET(JumpTest::Carry)),
+            0xd9 => Some(Instruction::RETI),
+            0xda => Some(Instruction::JP(JumpTest::Carry)),
+            0xdc => Some(Instruction::CALL(JumpTest::Carry)),
+            0xde => Some(Instruction::SBC(ArithmeticTarget::D8)),
+            0xdf => Some(Instruction::RST(RstVector::H18)),
+            0xe0 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::ByteIndirect))),
+            0xe1 => Some(Instruction::POP(StackTarget::HL)),
+            0xe2 => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::LastByteIndirect))),
+            0xe5 => Some(Instruction::PUSH(StackTarget::HL)),
+            0xe6 => Some(Instruction::AND(ArithmeticTarget::D8)),
+            0xe7 => Some(Instruction::RST(RstVector::H20)),
+            0xe8 => Some(Instruction::ADDSP),
+            0xe9 => Some(Instruction::JPHL),
+            0xea => Some(Instruction::LD(LoadType::IndirectFromA(Indirect::WordIndirect))),
+            0xee => Some(Instruction::XOR(ArithmeticTarget::D8)),
+            0xef => Some(Instruction::RST(RstVector::H28)),
+            0xf0 => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::ByteIndirect))),
+            0xf1 => Some(Instruction::POP(StackTarget::AF)),
+            0xf2 => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::LastByteIndirect))),
+            0xf3 => Some(Instruction::DI),
+            0xf5 => Some(Instruction::PUSH(StackTarget::AF)),
+            0xf6 => Some(Instruction::OR(ArithmeticTarget::D8)),
+            0xf7 => Some(Instruction::RST(RstVector::H30)),
+            0xf8 => Some(Instruction::LD(LoadType::HLFromSPN)),
+            0xf9 => Some(Instruction::LD(LoadType::SPFromHL)),
+            0xfa => Some(Instruction::LD(LoadType::AFromIndirect(Indirect::WordIndirect))),
+            0xfb => Some(Instruction::EI),
+            0xfe => Some(Instruction::CP(ArithmeticTarget::D8)),
+            0xff => Some(Instruction::RST(RstVector::H38)),
+            0xd3 | 0xdb | 0xdd | 0xe3 | 0xe4 | 0xeb | 0xec | 0xed | 0xf4 | 0xfc | 0xfd => {
+                Some(Instruction::ILLEGAL(byte))
             }
+            // 0xCB is the prefix byte, CPU::step reads the real opcode from the next byte
+            0xcb => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ILLEGAL_OPCODES: [u8; 11] = [
+        0xd3, 0xdb, 0xdd, 0xe3, 0xe4, 0xeb, 0xec, 0xed, 0xf4, 0xfc, 0xfd,
+    ];
+
+    #[test]
+    fn test_every_unprefixed_opcode_decodes() {
+        for byte in 0..=0xffu8 {
+            let instruction = Instruction::from_byte(byte, false);
+            if byte == 0xcb {
+                assert!(instruction.is_none());
+            } else if ILLEGAL_OPCODES.contains(&byte) {
+                assert!(matches!(instruction, Some(Instruction::ILLEGAL(b)) if b == byte));
+            } else {
+                assert!(
+                    !matches!(instruction, None | Some(Instruction::ILLEGAL(_))),
+                    "0x{:x} did not decode",
+                    byte
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_prefixed_opcode_decodes() {
+        for byte in 0..=0xffu8 {
+            assert!(Instruction::from_byte(byte, true).is_some(), "0xcb{:x} did not decode", byte);
+        }
+        assert!(matches!(
+            Instruction::from_byte(0x7c, true),
+            Some(Instruction::BIT(BitPosition::B7, PrefixTarget::H))
+        ));
+        assert!(matches!(
+            Instruction::from_byte(0x36, true),
+            Some(Instruction::SWAP(PrefixTarget::HLI))
+        ));
+        assert!(matches!(
+            Instruction::from_byte(0xbf, true),
+            Some(Instruction::RES(BitPosition::B7, PrefixTarget::A))
+        ));
+    }
+
+    #[test]
+    fn test_immediate_alu_decodes() {
+        assert!(matches!(
+            Instruction::from_byte(0xc6, false),
+            Some(Instruction::ADD(ArithmeticTarget::D8))
+        ));
+        assert!(matches!(
+            Instruction::from_byte(0xfe, false),
+            Some(Instruction::CP(ArithmeticTarget::D8))
+        ));
+    }
+}
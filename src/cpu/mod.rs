@@ -2,10 +2,20 @@ pub mod flags_register;
 pub mod instruction;
 pub mod registers;
 
+use crate::cartridge::header::CartridgeHeader;
+use crate::cartridge::rtc::{Clock, SystemClock};
+use crate::cartridge::CartridgeEvent;
+use crate::config::Config;
 use crate::cpu::instruction::ArithmeticTarget;
 use crate::cpu::instruction::Instruction;
 use crate::cpu::registers::Registers;
-use crate::memory_bus::MemoryBus;
+use crate::error::EmulationError;
+use crate::interrupts::Interrupt;
+use crate::joypad::Button;
+use crate::memory_bus::{AccessMode, MemoryBus, INTERRUPT_FLAG_REGISTER};
+use crate::model::Model;
+use crate::ppu::framebuffer::Framebuffer;
+use crate::serial::SerialPeer;
 
 use self::instruction::*;
 
@@ -14,6 +24,16 @@ pub struct CPU {
     pc: u16,
     sp: u16,
     bus: MemoryBus,
+    // Interrupt master enable
+    ime: bool,
+    // EI only takes effect after the instruction that follows it
+    ime_scheduled: bool,
+    is_halted: bool,
+    is_stopped: bool,
+    // Set when HALT is executed with IME=0 and an interrupt already pending
+    halt_bug: bool,
+    // T-cycles of the current step already clocked on the bus
+    ticked: u8,
 }
 
 macro_rules! manipulate_8bit_register {
@@ -26,6 +46,47 @@ macro_rules! manipulate_8bit_register {
     }};
 }
 
+// INC rr and DEC rr, neither of which touch the flags
+macro_rules! manipulate_16bit_register {
+    ($self:ident : $getter:ident => $work:ident => $setter:ident) => {{
+        let value = $self.registers.$getter().$work(1);
+        $self.registers.$setter(value);
+    }};
+}
+
+macro_rules! inc_dec_instruction {
+    ($target:ident, $self:ident.$work:ident, $word_work:ident) => {{
+        match $target {
+            IncDecTarget::A => manipulate_8bit_register!($self: a => $work, a),
+            IncDecTarget::B => manipulate_8bit_register!($self: b => $work, b),
+            IncDecTarget::C => manipulate_8bit_register!($self: c => $work, c),
+            IncDecTarget::D => manipulate_8bit_register!($self: d => $work, d),
+            IncDecTarget::E => manipulate_8bit_register!($self: e => $work, e),
+            IncDecTarget::H => manipulate_8bit_register!($self: h => $work, h),
+            IncDecTarget::L => manipulate_8bit_register!($self: l => $work, l),
+            IncDecTarget::HLI => {
+                let address = $self.registers.get_hl();
+                let value = $self.read(address);
+                let result = $self.$work(value);
+                $self.write(address, result);
+            },
+            IncDecTarget::BC => manipulate_16bit_register!($self: get_bc => $word_work => set_bc),
+            IncDecTarget::DE => manipulate_16bit_register!($self: get_de => $word_work => set_de),
+            IncDecTarget::HL => manipulate_16bit_register!($self: get_hl => $word_work => set_hl),
+            IncDecTarget::SP => $self.sp = $self.sp.$word_work(1),
+        };
+
+        match $target {
+            // (HL) is a read, modify and write back
+            IncDecTarget::HLI => ($self.pc.wrapping_add(1), 12),
+            IncDecTarget::BC | IncDecTarget::DE | IncDecTarget::HL | IncDecTarget::SP => {
+                ($self.pc.wrapping_add(1), 8)
+            },
+            _ => ($self.pc.wrapping_add(1), 4),
+        }
+    }};
+}
+
 #[macro_export]
 macro_rules! arithmetic_instruction {
     ($register:ident, $self:ident.$work:ident) => {{
@@ -38,13 +99,19 @@ macro_rules! arithmetic_instruction {
             ArithmeticTarget::H => manipulate_8bit_register!($self: h => $work),
             ArithmeticTarget::L => manipulate_8bit_register!($self: l => $work),
             ArithmeticTarget::HLI => {
-                let value = $self.bus.read_byte($self.registers.get_hl());
+                let value = $self.read($self.registers.get_hl());
+                $self.$work(value);
+            },
+            ArithmeticTarget::D8 => {
+                let value = $self.read_next_byte();
                 $self.$work(value);
             },
         };
 
         match $register {
-            _ => {($self.pc.wrapping_add(1), 1)}
+            ArithmeticTarget::HLI => ($self.pc.wrapping_add(1), 8),
+            ArithmeticTarget::D8 => ($self.pc.wrapping_add(2), 8),
+            _ => {($self.pc.wrapping_add(1), 4)}
         }
     }};
     ($register:ident, $self:ident.$work:ident => $result_register:ident) => {{
@@ -57,27 +124,87 @@ macro_rules! arithmetic_instruction {
             ArithmeticTarget::H => {manipulate_8bit_register!($self: h => $work, $result_register)},
             ArithmeticTarget::L => {manipulate_8bit_register!($self: l => $work, $result_register)},
             ArithmeticTarget::HLI => {
-                let value = $self.bus.read_byte($self.registers.get_hl());
+                let value = $self.read($self.registers.get_hl());
+                let result = $self.$work(value);
+                $self.registers.$result_register = result;
+            },
+            ArithmeticTarget::D8 => {
+                let value = $self.read_next_byte();
                 let result = $self.$work(value);
-                $self.registers.a = result;
+                $self.registers.$result_register = result;
             },
         };
 
         match $register {
             ArithmeticTarget::HLI => ($self.pc.wrapping_add(1), 8),
+            ArithmeticTarget::D8 => ($self.pc.wrapping_add(2), 8),
             _ => {($self.pc.wrapping_add(1), 4)}
         }
     }};
 }
 
-impl CPU {
-    pub fn new(boot_rom: Option<Vec<u8>>, game_rom: Vec<u8>) -> CPU {
-        CPU {
-            registers: Registers::new(),
-            pc: 0x0,
-            sp: 0x00,
-            bus: MemoryBus::new(boot_rom, game_rom),
+macro_rules! prefix_instruction {
+    ($target:ident, $self:ident.$work:ident $(, $arg:expr)*) => {{
+        let value = $self.read_prefix_target(&$target);
+        let result = $self.$work(value $(, $arg)*);
+        $self.write_prefix_target(&$target, result);
+
+        match $target {
+            PrefixTarget::HLI => ($self.pc.wrapping_add(2), 16),
+            _ => ($self.pc.wrapping_add(2), 8),
         }
+    }};
+}
+
+// RLCA, RRCA, RLA and RRA behave like their CB counterparts on A except Z is always cleared
+macro_rules! accumulator_rotate {
+    ($self:ident.$work:ident) => {{
+        manipulate_8bit_register!($self: a => $work, a);
+        $self.registers.f.zero = false;
+        ($self.pc.wrapping_add(1), 4)
+    }};
+}
+
+impl CPU {
+    pub fn new(boot_rom: Option<Vec<u8>>, game_rom: Vec<u8>) -> Result<CPU, EmulationError> {
+        CPU::with_model(Model::default(), boot_rom, game_rom)
+    }
+
+    pub fn with_model(
+        model: Model,
+        boot_rom: Option<Vec<u8>>,
+        game_rom: Vec<u8>,
+    ) -> Result<CPU, EmulationError> {
+        let config = Config { model, ..Config::default() };
+        CPU::with_config(config, boot_rom, game_rom, Box::new(SystemClock))
+    }
+
+    pub fn with_config(
+        config: Config,
+        boot_rom: Option<Vec<u8>>,
+        game_rom: Vec<u8>,
+        clock: Box<dyn Clock>,
+    ) -> Result<CPU, EmulationError> {
+        // Without a boot ROM start where it would have handed over to the cartridge
+        let skip_boot = boot_rom.is_none();
+        let bus = MemoryBus::with_config(config, boot_rom, game_rom, clock)?;
+        let (registers, pc, sp) = if skip_boot {
+            (Registers::post_boot(config.model, bus.cartridge_header()), 0x0100, 0xFFFE)
+        } else {
+            (Registers::new(), 0x0, 0x00)
+        };
+        Ok(CPU {
+            registers,
+            pc,
+            sp,
+            bus,
+            ime: false,
+            ime_scheduled: false,
+            is_halted: false,
+            is_stopped: false,
+            halt_bug: false,
+            ticked: 0,
+        })
     }
     fn execute(&mut self, instruction: Instruction) -> (u16, u8) {
         match instruction {
@@ -119,7 +246,7 @@ impl CPU {
                         LoadByteSource::H => self.registers.h,
                         LoadByteSource::L => self.registers.l,
                         LoadByteSource::D8 => self.read_next_byte(),
-                        LoadByteSource::HLI => self.bus.read_byte(self.registers.get_hl()),
+                        LoadByteSource::HLI => self.read(self.registers.get_hl()),
                     };
                     match target {
                         LoadByteTarget::A => self.registers.a = source_value,
@@ -130,12 +257,15 @@ impl CPU {
                         LoadByteTarget::H => self.registers.h = source_value,
                         LoadByteTarget::L => self.registers.l = source_value,
                         LoadByteTarget::HLI => {
-                            self.bus.write_byte(self.registers.get_hl(), source_value)
+                            self.write(self.registers.get_hl(), source_value)
                         }
                     };
-                    match source {
-                        LoadByteSource::D8 => (self.pc.wrapping_add(2), 8),
-                        LoadByteSource::HLI => (self.pc.wrapping_add(1), 8),
+                    match (target, source) {
+                        (LoadByteTarget::HLI, LoadByteSource::D8) => (self.pc.wrapping_add(2), 12),
+                        (_, LoadByteSource::D8) => (self.pc.wrapping_add(2), 8),
+                        (LoadByteTarget::HLI, _) | (_, LoadByteSource::HLI) => {
+                            (self.pc.wrapping_add(1), 8)
+                        }
                         _ => (self.pc.wrapping_add(1), 4),
                     }
                 }
@@ -150,70 +280,373 @@ impl CPU {
                     (self.pc.wrapping_add(3), 12)
                 }
                 LoadType::IndirectFromA(indirect) => {
-                    let a = self.registers.a;
-                    match indirect {
-                        Indirect::BCIndirect => {
-                            let mem_addr = self.registers.get_bc();
-                            self.bus.write_byte(mem_addr, a)
-                        },
-                        Indirect::DEIndirect => {
-                            let mem_addr = self.registers.get_de();
-                            self.bus.write_byte(mem_addr, a)
-                        },
-                        Indirect::HLIndirectMinus => {
-                            let mem_addr = self.registers.get_hl();
-                            self.registers.set_hl(mem_addr.wrapping_sub(1));
-                            self.bus.write_byte(mem_addr, a);
-                        },
-                        Indirect::HLIndirectPlus => {
-                            let mem_addr = self.registers.get_hl();
-                            self.registers.set_hl(mem_addr.wrapping_add(1));
-                            self.bus.write_byte(mem_addr, a);
-                        },
-                        Indirect::WordIndirect => {
-                            let mem_addr = self.read_next_word();
-                            self.bus.write_byte(mem_addr, a)
-                        },
-                        Indirect::LastByteIndirect => {
-                            let mem_addr = self.registers.c as u16 & 0xff00;
-                            self.bus.write_byte(mem_addr, a)
-                        }
-                    }
-                    match indirect {
-                        Indirect::WordIndirect => (self.pc.wrapping_add(3), 16),
-                        Indirect::LastByteIndirect => (self.pc.wrapping_add(2), 8),
-                        _ => (self.pc.wrapping_add(1), 8),
-                    }
+                    let mem_addr = self.indirect_address(&indirect);
+                    self.write(mem_addr, self.registers.a);
+                    self.indirect_length_and_cycles(&indirect)
+                }
+                LoadType::AFromIndirect(indirect) => {
+                    let mem_addr = self.indirect_address(&indirect);
+                    self.registers.a = self.read(mem_addr);
+                    self.indirect_length_and_cycles(&indirect)
+                }
+                LoadType::IndirectFromSP => {
+                    let mem_addr = self.read_next_word();
+                    self.write(mem_addr, (self.sp & 0x00FF) as u8);
+                    self.write(mem_addr.wrapping_add(1), ((self.sp & 0xFF00) >> 8) as u8);
+                    (self.pc.wrapping_add(3), 20)
                 }
+                LoadType::SPFromHL => {
+                    self.sp = self.registers.get_hl();
+                    (self.pc.wrapping_add(1), 8)
+                }
+                LoadType::HLFromSPN => {
+                    let value = self.add_sp_offset();
+                    self.registers.set_hl(value);
+                    (self.pc.wrapping_add(2), 12)
+                }
+            },
+            Instruction::DAA => {
+                self.registers.a = self.decimal_adjust(self.registers.a);
+                (self.pc.wrapping_add(1), 4)
+            },
+            Instruction::CPL => {
+                self.registers.a = !self.registers.a;
+                self.registers.f.subtract = true;
+                self.registers.f.half_carry = true;
+                (self.pc.wrapping_add(1), 4)
+            },
+            Instruction::SCF => {
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = true;
+                (self.pc.wrapping_add(1), 4)
+            },
+            Instruction::CCF => {
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = !self.registers.f.carry;
+                (self.pc.wrapping_add(1), 4)
+            },
+            Instruction::RLCA => {
+                accumulator_rotate!(self.rotate_left_circular)
+            },
+            Instruction::RRCA => {
+                accumulator_rotate!(self.rotate_right_circular)
+            },
+            Instruction::RLA => {
+                accumulator_rotate!(self.rotate_left_through_carry)
+            },
+            Instruction::RRA => {
+                accumulator_rotate!(self.rotate_right_through_carry)
+            },
+            Instruction::PUSH(target) => {
+                let value = match target {
+                    StackTarget::AF => self.registers.get_af(),
+                    StackTarget::BC => self.registers.get_bc(),
+                    StackTarget::DE => self.registers.get_de(),
+                    StackTarget::HL => self.registers.get_hl(),
+                };
+                self.push(value);
+                (self.pc.wrapping_add(1), 16)
+            },
+            Instruction::POP(target) => {
+                let value = self.pop();
+                match target {
+                    // The low nibble of F doesn't exist, set_af drops it
+                    StackTarget::AF => self.registers.set_af(value),
+                    StackTarget::BC => self.registers.set_bc(value),
+                    StackTarget::DE => self.registers.set_de(value),
+                    StackTarget::HL => self.registers.set_hl(value),
+                };
+                (self.pc.wrapping_add(1), 12)
+            },
+            Instruction::INC(target) => {
+                inc_dec_instruction!(target, self.increment, wrapping_add)
+            },
+            Instruction::DEC(target) => {
+                inc_dec_instruction!(target, self.decrement, wrapping_sub)
+            },
+            Instruction::ADDHL(target) => {
+                let value = match target {
+                    ADDHLTarget::BC => self.registers.get_bc(),
+                    ADDHLTarget::DE => self.registers.get_de(),
+                    ADDHLTarget::HL => self.registers.get_hl(),
+                    ADDHLTarget::SP => self.sp,
+                };
+                let result = self.add_hl(value);
+                self.registers.set_hl(result);
+                (self.pc.wrapping_add(1), 8)
+            },
+            Instruction::ADDSP => {
+                self.sp = self.add_sp_offset();
+                (self.pc.wrapping_add(2), 16)
             },
 
-            _ => {
-                panic!("TODO: support more instructions {:?}", instruction)
+            Instruction::JP(test) => {
+                let jump_condition = self.jump_test(test);
+                self.jump(jump_condition)
+            },
+            Instruction::JPHL => {
+                (self.registers.get_hl(), 4)
+            },
+            Instruction::JR(test) => {
+                let jump_condition = self.jump_test(test);
+                self.jump_relative(jump_condition)
+            },
+            Instruction::CALL(test) => {
+                let jump_condition = self.jump_test(test);
+                self.call(jump_condition)
+            },
+            Instruction::RET(JumpTest::Always) => {
+                (self.pop(), 16)
+            },
+            Instruction::RET(test) => {
+                let jump_condition = self.jump_test(test);
+                if jump_condition {
+                    // The condition is checked in an M-cycle of its own before popping
+                    self.tick();
+                    (self.pop(), 20)
+                } else {
+                    (self.pc.wrapping_add(1), 8)
+                }
+            },
+            Instruction::RETI => {
+                self.ime = true;
+                (self.pop(), 16)
+            },
+            Instruction::HALT => {
+                if !self.ime && self.bus.pending_interrupt().is_some() {
+                    self.halt_bug = true;
+                } else {
+                    self.is_halted = true;
+                }
+                (self.pc.wrapping_add(1), 4)
+            },
+            Instruction::STOP => {
+                self.bus.reset_divider();
+                // STOP doubles as the trigger for a CGB speed switch, the CPU carries on afterwards
+                if !self.bus.switch_speed() {
+                    self.is_stopped = true;
+                }
+                (self.pc.wrapping_add(2), 4)
+            },
+            Instruction::DI => {
+                self.ime = false;
+                self.ime_scheduled = false;
+                (self.pc.wrapping_add(1), 4)
+            },
+            Instruction::EI => {
+                self.ime_scheduled = true;
+                (self.pc.wrapping_add(1), 4)
+            },
+            Instruction::RST(vector) => {
+                self.push(self.pc.wrapping_add(1));
+                (u16::from(vector), 16)
+            },
+            Instruction::RLC(target) => {
+                prefix_instruction!(target, self.rotate_left_circular)
+            },
+            Instruction::RRC(target) => {
+                prefix_instruction!(target, self.rotate_right_circular)
+            },
+            Instruction::RL(target) => {
+                prefix_instruction!(target, self.rotate_left_through_carry)
+            },
+            Instruction::RR(target) => {
+                prefix_instruction!(target, self.rotate_right_through_carry)
+            },
+            Instruction::SLA(target) => {
+                prefix_instruction!(target, self.shift_left_arithmetic)
+            },
+            Instruction::SRA(target) => {
+                prefix_instruction!(target, self.shift_right_arithmetic)
+            },
+            Instruction::SWAP(target) => {
+                prefix_instruction!(target, self.swap_nibbles)
+            },
+            Instruction::SRL(target) => {
+                prefix_instruction!(target, self.shift_right_logical)
+            },
+            Instruction::BIT(bit, target) => {
+                let value = self.read_prefix_target(&target);
+                self.bit_test(value, bit);
+                match target {
+                    PrefixTarget::HLI => (self.pc.wrapping_add(2), 12),
+                    _ => (self.pc.wrapping_add(2), 8),
+                }
+            },
+            Instruction::RES(bit, target) => {
+                prefix_instruction!(target, self.reset_bit, bit)
+            },
+            Instruction::SET(bit, target) => {
+                prefix_instruction!(target, self.set_bit, bit)
+            },
+            Instruction::ILLEGAL(_) => {
+                // The CPU hangs on these, step reports them before getting here
+                (self.pc, 4)
             }
         }
     }
 
-    pub fn step(&mut self) -> u8 {
-        let mut instruction_byte = self.bus.read_byte(self.pc);
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.bus.request_interrupt(interrupt);
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.is_halted
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.is_stopped
+    }
+
+    pub fn model(&self) -> Model {
+        self.bus.model()
+    }
+
+    pub fn connect_serial(&mut self, peer: Box<dyn SerialPeer>) {
+        self.bus.connect_serial(peer);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.bus.press_button(button);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.bus.release_button(button);
+    }
+
+    // The finished frame after each VBlank, None until the next one completes
+    pub fn poll_frame(&mut self) -> Option<&Framebuffer> {
+        self.bus.poll_frame()
+    }
+
+    pub fn cartridge_header(&self) -> &CartridgeHeader {
+        self.bus.cartridge_header()
+    }
+
+    pub fn save_data(&mut self) -> Option<Vec<u8>> {
+        self.bus.save_data()
+    }
+
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.bus.battery_ram()
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) -> Result<(), EmulationError> {
+        self.bus.load_save_data(data)
+    }
+
+    pub fn poll_cartridge_event(&mut self) -> Option<CartridgeEvent> {
+        self.bus.poll_cartridge_event()
+    }
+
+    pub fn set_access_mode(&mut self, access_mode: AccessMode) {
+        self.bus.set_access_mode(access_mode);
+    }
+
+    // Runs one instruction, interrupt dispatch or idle period and returns the cycles it took.
+    // The rest of the system is clocked an M-cycle at a time as the CPU goes, so every memory
+    // access sees the timer, PPU and DMA as they are on that M-cycle.
+    // An unmapped memory access is reported after the instruction has completed using
+    // open bus values, so stepping again simply carries on from there.
+    pub fn step(&mut self) -> Result<u8, EmulationError> {
+        if self.is_stopped {
+            // Only a button press wakes the CPU from STOP, and nothing else is clocked meanwhile
+            if self.bus.read_byte(INTERRUPT_FLAG_REGISTER as u16) & Interrupt::Joypad.mask() == 0 {
+                return Ok(4);
+            }
+            self.is_stopped = false;
+        }
+
+        self.ticked = 0;
+        self.step_cpu()
+    }
+
+    fn step_cpu(&mut self) -> Result<u8, EmulationError> {
+        if self.is_halted {
+            // Any enabled interrupt ends HALT, even if IME is off it just won't be serviced
+            if self.bus.pending_interrupt().is_none() {
+                self.tick();
+                return Ok(4);
+            }
+            self.is_halted = false;
+        }
+
+        if self.ime && self.bus.pending_interrupt().is_some() {
+            let pc = self.pc;
+            let cycles = self.service_interrupt();
+            self.finish_cycles(cycles);
+            if let Some(error) = self.bus.take_fault() {
+                return Err(EmulationError::MemoryAccess { pc, opcode: None, error });
+            }
+            return Ok(cycles);
+        }
+
+        let pc = self.pc;
+        let enable_interrupts = self.ime_scheduled;
+        let opcode = self.read(self.pc);
+        let mut instruction_byte = opcode;
         let prefixed = instruction_byte == 0xCB;
+        if self.halt_bug {
+            // The HALT bug fails to increment PC after the fetch, so the opcode
+            // byte is read a second time as the following byte
+            self.halt_bug = false;
+            self.pc = self.pc.wrapping_sub(1);
+        }
         if prefixed {
-            instruction_byte = self.bus.read_byte(self.pc + 1);
+            instruction_byte = self.read(self.pc.wrapping_add(1));
         }
-        let (next_pc, cycles) = if let Some(instruction) = Instruction::from_byte(instruction_byte, prefixed)
-        {
-            self.execute(instruction)
-        } else {
-            let description = format!(
-                "0x{}{:x}",
-                if prefixed { "cb" } else { "" },
-                instruction_byte
-            );
-            panic!("Unkown instruction found for: {}", description)
+        let (next_pc, cycles) = match Instruction::from_byte(instruction_byte, prefixed) {
+            Some(Instruction::ILLEGAL(opcode)) => {
+                self.pc = pc;
+                return Err(EmulationError::IllegalInstruction { pc, opcode });
+            }
+            Some(instruction) => self.execute(instruction),
+            None => {
+                self.pc = pc;
+                return Err(EmulationError::UnknownInstruction {
+                    pc,
+                    opcode: instruction_byte,
+                    prefixed,
+                });
+            }
         };
 
         self.pc = next_pc;
-        cycles
+        self.finish_cycles(cycles);
+        // A DI straight after EI cancels the pending enable
+        if enable_interrupts && self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
+        }
+        if let Some(error) = self.bus.take_fault() {
+            return Err(EmulationError::MemoryAccess { pc, opcode: Some(opcode), error });
+        }
+        Ok(cycles)
+    }
+
+    // The interrupt is only picked once the high byte of PC has been pushed. A push that lands
+    // on IE can redirect the dispatch, or cancel it and leave PC at 0x0000
+    fn service_interrupt(&mut self) -> u8 {
+        self.ime = false;
+        self.tick();
+        self.tick();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, (self.pc >> 8) as u8);
+        let interrupt = self.bus.pending_interrupt();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, self.pc as u8);
+        self.pc = match interrupt {
+            Some(interrupt) => {
+                self.bus.acknowledge_interrupt(interrupt);
+                interrupt.vector()
+            }
+            None => 0x0000,
+        };
+        20
     }
+
     fn sub_with_carry(&mut self, value: u8) -> u8 {
         self.sub(value, true)
     }
@@ -227,7 +660,7 @@ impl CPU {
 
         let (new_value, overflow) = self.registers.a.overflowing_sub(value);
         let (new_value2, overflow2) = new_value.overflowing_sub(carry_value);
-        self.registers.f.zero = new_value == 0;
+        self.registers.f.zero = new_value2 == 0;
         self.registers.f.subtract = true;
         self.registers.f.carry = overflow || overflow2;
         self.registers.f.half_carry = (self.registers.a & 0xF) < (value & 0xF) + carry_value;
@@ -246,7 +679,7 @@ impl CPU {
         let carry_value:u8 = (carry && self.registers.f.carry) as u8;
         let (new_value, overflow) = self.registers.a.overflowing_add(value);
         let (new_value2, overflow2) = new_value.overflowing_add(carry_value);
-        self.registers.f.zero = new_value == 0;
+        self.registers.f.zero = new_value2 == 0;
         self.registers.f.subtract = false;
         self.registers.f.carry = overflow || overflow2;
         // Half Carry is set if adding the lower nibbles of the value and register A
@@ -257,6 +690,75 @@ impl CPU {
         new_value2
     }
 
+    // INC and DEC leave the carry flag alone
+    fn increment(&mut self, value: u8) -> u8 {
+        let new_value = value.wrapping_add(1);
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = value & 0xF == 0xF;
+        new_value
+    }
+
+    fn decrement(&mut self, value: u8) -> u8 {
+        let new_value = value.wrapping_sub(1);
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.subtract = true;
+        // Borrowing from bit 4 only happens when the low nibble was 0
+        self.registers.f.half_carry = value & 0xF == 0;
+        new_value
+    }
+
+    fn add_hl(&mut self, value: u16) -> u16 {
+        let hl = self.registers.get_hl();
+        let (new_value, overflow) = hl.overflowing_add(value);
+        self.registers.f.subtract = false;
+        self.registers.f.carry = overflow;
+        // For 16 bit adds the half carry is out of bit 11
+        self.registers.f.half_carry = (hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF;
+        new_value
+    }
+
+    // SP plus the signed immediate byte, shared by ADD SP,e8 and LD HL,SP+e8.
+    // Both compute H and C as if adding the unsigned byte to the low byte of SP.
+    fn add_sp_offset(&mut self) -> u16 {
+        let offset = self.read_next_byte();
+        let sp = self.sp;
+        self.registers.f.clear();
+        self.registers.f.half_carry = (sp & 0x000F) + (offset as u16 & 0x000F) > 0x000F;
+        self.registers.f.carry = (sp & 0x00FF) + offset as u16 > 0x00FF;
+        sp.wrapping_add(offset as i8 as u16)
+    }
+
+    // Corrects A after a BCD addition or subtraction using the N, H and C flags it left behind
+    fn decimal_adjust(&mut self, value: u8) -> u8 {
+        let flags = self.registers.f;
+        let mut carry = flags.carry;
+        let new_value = if flags.subtract {
+            let mut adjustment = 0;
+            if flags.half_carry {
+                adjustment |= 0x06;
+            }
+            if flags.carry {
+                adjustment |= 0x60;
+            }
+            value.wrapping_sub(adjustment)
+        } else {
+            let mut adjustment = 0;
+            if flags.half_carry || (value & 0x0F) > 0x09 {
+                adjustment |= 0x06;
+            }
+            if flags.carry || value > 0x99 {
+                adjustment |= 0x60;
+                carry = true;
+            }
+            value.wrapping_add(adjustment)
+        };
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = carry;
+        new_value
+    }
+
     fn and(&mut self, value: u8) -> u8 {
         let new_value = self.registers.a & value;
         self.registers.f.clear();
@@ -287,12 +789,974 @@ impl CPU {
         self.registers.f.carry = self.registers.a < value;
     }
 
-    fn read_next_byte(&self) -> u8 {
-        self.bus.read_byte(self.pc + 1)
+    // Resolves the address an indirect load goes through, applying the HL+/HL- side effect
+    fn indirect_address(&mut self, indirect: &Indirect) -> u16 {
+        match indirect {
+            Indirect::BCIndirect => self.registers.get_bc(),
+            Indirect::DEIndirect => self.registers.get_de(),
+            Indirect::HLIndirectMinus => {
+                let mem_addr = self.registers.get_hl();
+                self.registers.set_hl(mem_addr.wrapping_sub(1));
+                mem_addr
+            },
+            Indirect::HLIndirectPlus => {
+                let mem_addr = self.registers.get_hl();
+                self.registers.set_hl(mem_addr.wrapping_add(1));
+                mem_addr
+            },
+            Indirect::WordIndirect => self.read_next_word(),
+            // LDH addresses the high page at 0xFF00
+            Indirect::ByteIndirect => 0xff00 | self.read_next_byte() as u16,
+            Indirect::LastByteIndirect => 0xff00 | self.registers.c as u16,
+        }
+    }
+
+    fn indirect_length_and_cycles(&self, indirect: &Indirect) -> (u16, u8) {
+        match indirect {
+            Indirect::WordIndirect => (self.pc.wrapping_add(3), 16),
+            Indirect::ByteIndirect => (self.pc.wrapping_add(2), 12),
+            _ => (self.pc.wrapping_add(1), 8),
+        }
     }
 
-    fn read_next_word(&self) -> u16 {
+    fn jump_test(&self, test: JumpTest) -> bool {
+        match test {
+            JumpTest::NotZero => !self.registers.f.zero,
+            JumpTest::Zero => self.registers.f.zero,
+            JumpTest::NotCarry => !self.registers.f.carry,
+            JumpTest::Carry => self.registers.f.carry,
+            JumpTest::Always => true,
+        }
+    }
+
+    fn jump(&mut self, should_jump: bool) -> (u16, u8) {
+        if should_jump {
+            (self.read_next_word(), 16)
+        } else {
+            (self.pc.wrapping_add(3), 12)
+        }
+    }
+
+    fn jump_relative(&mut self, should_jump: bool) -> (u16, u8) {
+        let next_pc = self.pc.wrapping_add(2);
+        if should_jump {
+            // The offset is a signed byte relative to the instruction following the JR
+            let offset = self.read_next_byte() as i8;
+            (next_pc.wrapping_add(offset as u16), 12)
+        } else {
+            (next_pc, 8)
+        }
+    }
+
+    fn call(&mut self, should_jump: bool) -> (u16, u8) {
+        let next_pc = self.pc.wrapping_add(3);
+        if should_jump {
+            let address = self.read_next_word();
+            self.push(next_pc);
+            (address, 24)
+        } else {
+            (next_pc, 12)
+        }
+    }
+
+    // Spends an internal M-cycle before the two writes, as PUSH, CALL and RST all do
+    fn push(&mut self, value: u16) {
+        self.tick();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, ((value & 0xFF00) >> 8) as u8);
+
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(self.sp, (value & 0x00FF) as u8);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let lsb = self.read(self.sp) as u16;
+        self.sp = self.sp.wrapping_add(1);
+
+        let msb = self.read(self.sp) as u16;
+        self.sp = self.sp.wrapping_add(1);
+
+        (msb << 8) | lsb
+    }
+
+    fn rotate_left_circular(&mut self, value: u8) -> u8 {
+        let new_value = value.rotate_left(1);
+        self.set_shift_flags(new_value, value & 0x80 != 0);
+        new_value
+    }
+
+    fn rotate_right_circular(&mut self, value: u8) -> u8 {
+        let new_value = value.rotate_right(1);
+        self.set_shift_flags(new_value, value & 0x01 != 0);
+        new_value
+    }
+
+    fn rotate_left_through_carry(&mut self, value: u8) -> u8 {
+        let new_value = (value << 1) | self.registers.f.carry as u8;
+        self.set_shift_flags(new_value, value & 0x80 != 0);
+        new_value
+    }
+
+    fn rotate_right_through_carry(&mut self, value: u8) -> u8 {
+        let new_value = (value >> 1) | ((self.registers.f.carry as u8) << 7);
+        self.set_shift_flags(new_value, value & 0x01 != 0);
+        new_value
+    }
+
+    fn shift_left_arithmetic(&mut self, value: u8) -> u8 {
+        let new_value = value << 1;
+        self.set_shift_flags(new_value, value & 0x80 != 0);
+        new_value
+    }
+
+    fn shift_right_arithmetic(&mut self, value: u8) -> u8 {
+        // The sign bit stays where it is
+        let new_value = (value >> 1) | (value & 0x80);
+        self.set_shift_flags(new_value, value & 0x01 != 0);
+        new_value
+    }
+
+    fn shift_right_logical(&mut self, value: u8) -> u8 {
+        let new_value = value >> 1;
+        self.set_shift_flags(new_value, value & 0x01 != 0);
+        new_value
+    }
+
+    fn swap_nibbles(&mut self, value: u8) -> u8 {
+        let new_value = value.rotate_left(4);
+        self.set_shift_flags(new_value, false);
+        new_value
+    }
+
+    fn set_shift_flags(&mut self, new_value: u8, carry: bool) {
+        self.registers.f.clear();
+        self.registers.f.zero = new_value == 0;
+        self.registers.f.carry = carry;
+    }
+
+    fn bit_test(&mut self, value: u8, bit: BitPosition) {
+        let bit = u8::from(bit);
+        self.registers.f.zero = (value >> bit) & 0b1 == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = true;
+    }
+
+    fn reset_bit(&mut self, value: u8, bit: BitPosition) -> u8 {
+        value & !(1 << u8::from(bit))
+    }
+
+    fn set_bit(&mut self, value: u8, bit: BitPosition) -> u8 {
+        value | (1 << u8::from(bit))
+    }
+
+    fn read_prefix_target(&mut self, target: &PrefixTarget) -> u8 {
+        match target {
+            PrefixTarget::A => self.registers.a,
+            PrefixTarget::B => self.registers.b,
+            PrefixTarget::C => self.registers.c,
+            PrefixTarget::D => self.registers.d,
+            PrefixTarget::E => self.registers.e,
+            PrefixTarget::H => self.registers.h,
+            PrefixTarget::L => self.registers.l,
+            PrefixTarget::HLI => self.read(self.registers.get_hl()),
+        }
+    }
+
+    fn write_prefix_target(&mut self, target: &PrefixTarget, value: u8) {
+        match target {
+            PrefixTarget::A => self.registers.a = value,
+            PrefixTarget::B => self.registers.b = value,
+            PrefixTarget::C => self.registers.c = value,
+            PrefixTarget::D => self.registers.d = value,
+            PrefixTarget::E => self.registers.e = value,
+            PrefixTarget::H => self.registers.h = value,
+            PrefixTarget::L => self.registers.l = value,
+            PrefixTarget::HLI => self.write(self.registers.get_hl(), value),
+        }
+    }
+
+    fn read_next_byte(&mut self) -> u8 {
+        self.read(self.pc.wrapping_add(1))
+    }
+
+    fn read_next_word(&mut self) -> u16 {
         //Gameboy is little endian so the second byte as first half of the word
-        ((self.bus.read_byte(self.pc + 2) as u16) << 8) | (self.bus.read_byte(self.pc) + 1) as u16
+        let low = self.read(self.pc.wrapping_add(1)) as u16;
+        let high = self.read(self.pc.wrapping_add(2)) as u16;
+        (high << 8) | low
+    }
+
+    // Clocks the rest of the system through one M-cycle
+    fn tick(&mut self) {
+        self.bus.step(4);
+        self.ticked += 4;
+    }
+
+    // Every access takes an M-cycle and lands at its end, after the timer, PPU and DMA moved on
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
+        self.bus.read_byte(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.tick();
+        self.bus.write_byte(address, value);
+    }
+
+    // Clocks whatever an instruction spent on internal work rather than bus accesses
+    fn finish_cycles(&mut self, cycles: u8) {
+        while self.ticked < cycles {
+            self.tick();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::BusError;
+    use crate::ppu::framebuffer::{SCREEN_HEIGHT, SCREEN_WIDTH};
+    use crate::ppu::{PpuBackend, DOTS_PER_LINE, LINES_PER_FRAME, OAM_SCAN_DOTS};
+
+    fn cpu_with_program(program: &[u8]) -> CPU {
+        let mut game_rom = vec![0; 0x8000];
+        game_rom[..program.len()].copy_from_slice(program);
+        let mut cpu = CPU::new(None, game_rom).unwrap();
+        // Run the program from address 0 with a clean slate rather than the post-boot state
+        cpu.registers = Registers::new();
+        cpu.pc = 0;
+        cpu.sp = 0;
+        cpu.bus.write_byte(0xff0f, 0x00);
+        cpu
+    }
+
+    #[test]
+    fn test_new_without_boot_rom_starts_at_cartridge_entry() {
+        let mut game_rom = vec![0; 0x8000];
+        game_rom[0x014d] = 0x42;
+        let cpu = CPU::new(None, game_rom.clone()).unwrap();
+        assert_eq!(cpu.pc, 0x0100);
+        assert_eq!(cpu.sp, 0xfffe);
+        assert_eq!(cpu.registers.get_af(), 0x01b0);
+        assert_eq!(cpu.bus.read_byte(0xff0f), 0xe1);
+
+        let cpu = CPU::new(Some(vec![0; 0x100]), game_rom).unwrap();
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(cpu.sp, 0x0000);
+    }
+
+    #[test]
+    fn test_rlc_sets_carry_from_bit_7() {
+        let mut cpu = cpu_with_program(&[0xcb, 0x00]);
+        cpu.registers.b = 0b1000_0001;
+        let cycles = cpu.step().unwrap();
+        assert_eq!(cpu.registers.b, 0b0000_0011);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.zero);
+        assert_eq!(cpu.pc, 2);
+        assert_eq!(cycles, 8);
+    }
+
+    #[test]
+    fn test_rr_shifts_in_old_carry() {
+        let mut cpu = cpu_with_program(&[0xcb, 0x19]);
+        cpu.registers.c = 0b0000_0001;
+        cpu.registers.f.carry = false;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.c, 0);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_sra_keeps_sign_bit() {
+        let mut cpu = cpu_with_program(&[0xcb, 0x2f]);
+        cpu.registers.a = 0b1000_0010;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0b1100_0001);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_swap_hl_indirect() {
+        let mut cpu = cpu_with_program(&[0xcb, 0x36]);
+        cpu.registers.set_hl(0xff80);
+        cpu.bus.write_byte(0xff80, 0xf1);
+        cpu.registers.f.carry = true;
+        let cycles = cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xff80), 0x1f);
+        assert!(!cpu.registers.f.carry);
+        assert_eq!(cycles, 16);
+    }
+
+    #[test]
+    fn test_bit_leaves_carry_alone() {
+        let mut cpu = cpu_with_program(&[0xcb, 0x7c, 0xcb, 0x46]);
+        cpu.registers.h = 0x80;
+        cpu.registers.f.carry = true;
+        cpu.step().unwrap();
+        assert!(!cpu.registers.f.zero);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+
+        cpu.registers.set_hl(0xff80);
+        cpu.bus.write_byte(0xff80, 0xfe);
+        let cycles = cpu.step().unwrap();
+        assert!(cpu.registers.f.zero);
+        assert_eq!(cycles, 12);
+    }
+
+    #[test]
+    fn test_res_and_set() {
+        let mut cpu = cpu_with_program(&[0xcb, 0x87, 0xcb, 0xf8]);
+        cpu.registers.a = 0xff;
+        cpu.registers.b = 0x00;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0xfe);
+        assert_eq!(cpu.registers.b, 0x80);
+    }
+
+    #[test]
+    fn test_jr_conditional_cycles() {
+        // JR NZ,+2 with Z set falls through, then JR Z,-4 jumps back
+        let mut cpu = cpu_with_program(&[0x20, 0x02, 0x28, 0xfc]);
+        cpu.registers.f.zero = true;
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.pc, 2);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.pc, 0);
+    }
+
+    #[test]
+    fn test_jp_absolute() {
+        let mut cpu = cpu_with_program(&[0xc3, 0x34, 0x12]);
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.pc, 0x1234);
+    }
+
+    #[test]
+    fn test_call_and_ret() {
+        let mut program = vec![0xcd, 0x10, 0x00];
+        program.resize(0x10, 0x00);
+        program.push(0xc9);
+        let mut cpu = cpu_with_program(&program);
+        cpu.sp = 0xfffe;
+        assert_eq!(cpu.step().unwrap(), 24);
+        assert_eq!(cpu.pc, 0x10);
+        assert_eq!(cpu.sp, 0xfffc);
+        assert_eq!(cpu.bus.read_byte(0xfffc), 0x03);
+        assert_eq!(cpu.bus.read_byte(0xfffd), 0x00);
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.pc, 0x03);
+        assert_eq!(cpu.sp, 0xfffe);
+    }
+
+    #[test]
+    fn test_conditional_ret_not_taken() {
+        let mut cpu = cpu_with_program(&[0xd8]);
+        cpu.registers.f.carry = false;
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.pc, 1);
+    }
+
+    #[test]
+    fn test_rst_pushes_next_pc() {
+        let mut cpu = cpu_with_program(&[0x00, 0xef]);
+        cpu.sp = 0xd000;
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.pc, 0x28);
+        assert_eq!(cpu.pop(), 0x0002);
+    }
+
+    #[test]
+    fn test_ei_takes_effect_after_next_instruction() {
+        let mut cpu = cpu_with_program(&[0xfb, 0x00, 0x00]);
+        cpu.sp = 0xfffe;
+        cpu.bus.write_byte(0xffff, 0xff);
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.step().unwrap();
+        assert!(!cpu.ime);
+        cpu.step().unwrap();
+        assert!(cpu.ime);
+        assert_eq!(cpu.pc, 2);
+        assert_eq!(cpu.step().unwrap(), 20);
+        assert_eq!(cpu.pc, 0x50);
+        assert!(!cpu.ime);
+        assert_eq!(cpu.pop(), 2);
+        assert_eq!(cpu.bus.read_byte(0xff0f) & 0x1f, 0);
+    }
+
+    #[test]
+    fn test_di_after_ei_keeps_interrupts_disabled() {
+        let mut cpu = cpu_with_program(&[0xfb, 0xf3, 0x00]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(!cpu.ime);
+    }
+
+    #[test]
+    fn test_interrupts_dispatch_in_priority_order() {
+        let mut cpu = cpu_with_program(&[0x00]);
+        cpu.sp = 0xfffe;
+        cpu.ime = true;
+        cpu.bus.write_byte(0xffff, 0xff);
+        cpu.request_interrupt(Interrupt::Joypad);
+        cpu.request_interrupt(Interrupt::Serial);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x58);
+    }
+
+    #[test]
+    fn test_push_over_ie_redirects_or_cancels_dispatch() {
+        // With SP at 0x0000 the high byte of PC lands on IE, here 0x02 which only enables STAT
+        for &(requested, vector, left) in [(0x01, 0x0000, 0x01), (0x03, 0x0048, 0x01)].iter() {
+            let mut cpu = cpu_with_program(&[]);
+            cpu.pc = 0x0200;
+            cpu.ime = true;
+            cpu.bus.write_byte(0xffff, 0x01);
+            cpu.bus.write_byte(0xff0f, requested);
+            assert_eq!(cpu.step(), Ok(20));
+            assert_eq!(cpu.pc, vector);
+            assert_eq!(cpu.sp, 0xfffe);
+            assert_eq!(cpu.bus.read_byte(0xffff), 0x02);
+            assert_eq!(cpu.bus.read_byte(0xff0f) & 0x1f, left);
+        }
+    }
+
+    #[test]
+    fn test_reti_enables_interrupts_immediately() {
+        let mut cpu = cpu_with_program(&[0xd9]);
+        cpu.sp = 0xfffe;
+        cpu.push(0x1234);
+        cpu.step().unwrap();
+        assert!(cpu.ime);
+        assert_eq!(cpu.pc, 0x1234);
+    }
+
+    #[test]
+    fn test_halt_idles_until_interrupt_pending() {
+        let mut cpu = cpu_with_program(&[0x76, 0x80]);
+        cpu.registers.b = 1;
+        cpu.bus.write_byte(0xffff, 0x04);
+        cpu.step().unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.pc, 1);
+
+        // IME is off, so the CPU wakes up and carries on without servicing it
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.pc, 2);
+        assert_eq!(cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn test_halt_wakes_into_interrupt_with_ime() {
+        let mut cpu = cpu_with_program(&[0x76, 0x00]);
+        cpu.sp = 0xfffe;
+        cpu.ime = true;
+        cpu.bus.write_byte(0xffff, 0x01);
+        cpu.step().unwrap();
+        cpu.request_interrupt(Interrupt::VBlank);
+        assert_eq!(cpu.step().unwrap(), 20);
+        assert_eq!(cpu.pc, 0x40);
+        assert_eq!(cpu.pop(), 1);
+    }
+
+    #[test]
+    fn test_halt_bug_reads_next_byte_twice() {
+        // HALT; ADD A,B; with the bug ADD A,B runs twice
+        let mut cpu = cpu_with_program(&[0x76, 0x80, 0x00]);
+        cpu.registers.b = 1;
+        cpu.bus.write_byte(0xffff, 0x01);
+        cpu.request_interrupt(Interrupt::VBlank);
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 1);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 2);
+        assert_eq!(cpu.registers.a, 2);
+    }
+
+    #[test]
+    fn test_stop_resets_divider_and_waits_for_joypad() {
+        let mut cpu = cpu_with_program(&[0x00, 0x10, 0x00, 0x00]);
+        for _ in 0..100 {
+            cpu.bus.step(4);
+        }
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.is_stopped());
+        assert_eq!(cpu.bus.read_byte(0xff04), 0);
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.pc, 3);
+        cpu.request_interrupt(Interrupt::Joypad);
+        cpu.step().unwrap();
+        assert!(!cpu.is_stopped());
+        assert_eq!(cpu.pc, 4);
+    }
+
+    #[test]
+    fn test_stop_performs_armed_speed_switch() {
+        let mut game_rom = vec![0; 0x8000];
+        game_rom[0x0100] = 0x10;
+        let mut cpu = CPU::with_model(Model::CGB, None, game_rom.clone()).unwrap();
+        cpu.bus.write_byte(0xff4d, 0x01);
+        cpu.step().unwrap();
+        assert!(!cpu.is_stopped());
+        assert!(cpu.bus.is_double_speed());
+
+        // There is no KEY1 to arm on a DMG, so STOP really stops
+        let mut cpu = CPU::with_model(Model::DMG, None, game_rom).unwrap();
+        cpu.bus.write_byte(0xff4d, 0x01);
+        cpu.step().unwrap();
+        assert!(cpu.is_stopped());
+        assert!(!cpu.bus.is_double_speed());
+    }
+
+    #[test]
+    fn test_with_model_sets_post_boot_registers() {
+        let mut game_rom = vec![0; 0x8000];
+        game_rom[0x0143] = 0x80;
+        let cpu = CPU::with_model(Model::AGB, None, game_rom.clone()).unwrap();
+        assert_eq!(cpu.model(), Model::AGB);
+        assert_eq!(cpu.registers.a, 0x11);
+        assert_eq!(cpu.registers.b, 0x01);
+
+        assert!(CPU::with_model(Model::CGB, Some(vec![0; 0x100]), game_rom.clone()).is_err());
+        let cpu = CPU::with_model(Model::CGB, Some(vec![0; 0x900]), game_rom).unwrap();
+        assert_eq!(cpu.pc, 0x0000);
+    }
+
+    #[test]
+    fn test_push_pop_round_trip() {
+        let mut cpu = cpu_with_program(&[0xc5, 0xd1]);
+        cpu.sp = 0xfffe;
+        cpu.registers.set_bc(0xbeef);
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.sp, 0xfffc);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.registers.get_de(), 0xbeef);
+        assert_eq!(cpu.sp, 0xfffe);
+    }
+
+    #[test]
+    fn test_pop_af_masks_low_nibble() {
+        let mut cpu = cpu_with_program(&[0xf1]);
+        cpu.sp = 0xfffe;
+        cpu.push(0x12ff);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x12);
+        assert_eq!(cpu.registers.get_af(), 0x12f0);
+    }
+
+    #[test]
+    fn test_inc_dec_16bit_leave_flags() {
+        let mut cpu = cpu_with_program(&[0x03, 0x1b, 0x33]);
+        cpu.registers.set_bc(0xffff);
+        cpu.registers.f.zero = false;
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.registers.get_bc(), 0x0000);
+        assert!(!cpu.registers.f.zero);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.get_de(), 0xffff);
+        cpu.step().unwrap();
+        assert_eq!(cpu.sp, 0x0001);
+    }
+
+    #[test]
+    fn test_add_hl_carries_from_bit_11_and_15() {
+        let mut cpu = cpu_with_program(&[0x09, 0x29]);
+        cpu.registers.set_hl(0x0fff);
+        cpu.registers.set_bc(0x0001);
+        cpu.registers.f.zero = true;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.get_hl(), 0x1000);
+        assert!(cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+        assert!(cpu.registers.f.zero);
+
+        cpu.registers.set_hl(0x8000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.get_hl(), 0x0000);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_add_sp_flags_from_low_byte() {
+        let mut cpu = cpu_with_program(&[0xe8, 0xff]);
+        cpu.sp = 0x00ff;
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.sp, 0x00fe);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.zero);
+        assert!(!cpu.registers.f.subtract);
+    }
+
+    #[test]
+    fn test_ld_hl_sp_offset() {
+        let mut cpu = cpu_with_program(&[0xf8, 0x02]);
+        cpu.sp = 0xfff8;
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.registers.get_hl(), 0xfffa);
+        assert_eq!(cpu.sp, 0xfff8);
+        assert!(!cpu.registers.f.half_carry);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_ld_a16_sp_and_sp_hl() {
+        let mut cpu = cpu_with_program(&[0x08, 0x80, 0xff, 0xf9]);
+        cpu.sp = 0xabcd;
+        assert_eq!(cpu.step().unwrap(), 20);
+        assert_eq!(cpu.bus.read_byte(0xff80), 0xcd);
+        assert_eq!(cpu.bus.read_byte(0xff81), 0xab);
+        cpu.registers.set_hl(0x1234);
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.sp, 0x1234);
+    }
+
+    #[test]
+    fn test_daa_after_bcd_add() {
+        // 0x45 + 0x38 = 0x7d, adjusted to BCD 83
+        let mut cpu = cpu_with_program(&[0x80, 0x27]);
+        cpu.registers.a = 0x45;
+        cpu.registers.b = 0x38;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x83);
+        assert!(!cpu.registers.f.carry);
+        assert!(!cpu.registers.f.half_carry);
+    }
+
+    #[test]
+    fn test_daa_after_bcd_add_with_carry_out() {
+        // 99 + 01 = 100, A wraps to 00 with carry and zero set
+        let mut cpu = cpu_with_program(&[0x80, 0x27]);
+        cpu.registers.a = 0x99;
+        cpu.registers.b = 0x01;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.carry);
+        assert!(cpu.registers.f.zero);
+    }
+
+    #[test]
+    fn test_daa_after_bcd_sub() {
+        // 42 - 15 = 27
+        let mut cpu = cpu_with_program(&[0x90, 0x27]);
+        cpu.registers.a = 0x42;
+        cpu.registers.b = 0x15;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x27);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_adc_zero_flag_includes_carry() {
+        let mut cpu = cpu_with_program(&[0x88]);
+        cpu.registers.a = 0xff;
+        cpu.registers.b = 0x00;
+        cpu.registers.f.carry = true;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_cpl_scf_ccf() {
+        let mut cpu = cpu_with_program(&[0x2f, 0x37, 0x3f]);
+        cpu.registers.a = 0b1010_0101;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0b0101_1010);
+        assert!(cpu.registers.f.subtract && cpu.registers.f.half_carry);
+        cpu.step().unwrap();
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.subtract && !cpu.registers.f.half_carry);
+        cpu.step().unwrap();
+        assert!(!cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_accumulator_rotates_clear_zero() {
+        let mut cpu = cpu_with_program(&[0x07, 0x17, 0x1f, 0x0f]);
+        cpu.registers.a = 0x00;
+        cpu.registers.f.zero = true;
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert!(!cpu.registers.f.zero);
+
+        cpu.registers.a = 0x80;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.zero);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x80);
+        assert!(!cpu.registers.f.carry);
+
+        cpu.registers.a = 0x01;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x80);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_register_to_register_load() {
+        let mut cpu = cpu_with_program(&[0x41, 0x7e, 0x70]);
+        cpu.registers.c = 0x42;
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.registers.b, 0x42);
+
+        cpu.registers.set_hl(0xff90);
+        cpu.bus.write_byte(0xff90, 0x99);
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.registers.a, 0x99);
+
+        cpu.registers.set_hl(0xff91);
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.bus.read_byte(0xff91), 0x42);
+    }
+
+    #[test]
+    fn test_load_immediate_into_hl_indirect() {
+        let mut cpu = cpu_with_program(&[0x36, 0x5a, 0x0e, 0x07]);
+        cpu.registers.set_hl(0xff80);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.bus.read_byte(0xff80), 0x5a);
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.registers.c, 0x07);
+        assert_eq!(cpu.pc, 4);
+    }
+
+    #[test]
+    fn test_load_a_from_hl_plus_and_minus() {
+        let mut cpu = cpu_with_program(&[0x2a, 0x3a]);
+        cpu.registers.set_hl(0xff80);
+        cpu.bus.write_byte(0xff80, 0x11);
+        cpu.bus.write_byte(0xff81, 0x22);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x11);
+        assert_eq!(cpu.registers.get_hl(), 0xff81);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x22);
+        assert_eq!(cpu.registers.get_hl(), 0xff80);
+    }
+
+    #[test]
+    fn test_load_a_from_word_indirect() {
+        let mut cpu = cpu_with_program(&[0xfa, 0x85, 0xff]);
+        cpu.bus.write_byte(0xff85, 0x77);
+        assert_eq!(cpu.step().unwrap(), 16);
+        assert_eq!(cpu.registers.a, 0x77);
+        assert_eq!(cpu.pc, 3);
+    }
+
+    #[test]
+    fn test_ldh_round_trip() {
+        let mut cpu = cpu_with_program(&[0xe0, 0x80, 0xaf, 0xf0, 0x80]);
+        cpu.registers.a = 0x3c;
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.bus.read_byte(0xff80), 0x3c);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.registers.a, 0x3c);
+        assert_eq!(cpu.pc, 5);
+    }
+
+    #[test]
+    fn test_ld_c_indirect_addresses_high_page() {
+        let mut cpu = cpu_with_program(&[0xe2, 0xaf, 0xf2]);
+        cpu.registers.a = 0x66;
+        cpu.registers.c = 0x82;
+        assert_eq!(cpu.step().unwrap(), 8);
+        assert_eq!(cpu.pc, 1);
+        assert_eq!(cpu.bus.read_byte(0xff82), 0x66);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x66);
+    }
+
+    #[test]
+    fn test_inc_half_carry_preserves_carry() {
+        let mut cpu = cpu_with_program(&[0x0c, 0x3c]);
+        cpu.registers.c = 0x0f;
+        cpu.registers.f.carry = true;
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.registers.c, 0x10);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.carry);
+        assert!(!cpu.registers.f.subtract);
+
+        cpu.registers.a = 0xff;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.a, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(cpu.registers.f.carry);
+    }
+
+    #[test]
+    fn test_dec_borrow_and_zero() {
+        let mut cpu = cpu_with_program(&[0x05, 0x05]);
+        cpu.registers.b = 0x10;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.b, 0x0f);
+        assert!(cpu.registers.f.half_carry);
+        assert!(cpu.registers.f.subtract);
+        assert!(!cpu.registers.f.zero);
+
+        cpu.registers.b = 0x01;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.b, 0x00);
+        assert!(cpu.registers.f.zero);
+        assert!(!cpu.registers.f.half_carry);
+    }
+
+    #[test]
+    fn test_inc_dec_hl_indirect() {
+        let mut cpu = cpu_with_program(&[0x34, 0x35, 0x35]);
+        cpu.registers.set_hl(0xff80);
+        cpu.bus.write_byte(0xff80, 0x7f);
+        assert_eq!(cpu.step().unwrap(), 12);
+        assert_eq!(cpu.bus.read_byte(0xff80), 0x80);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.read_byte(0xff80), 0x7e);
+        assert_eq!(cpu.registers.get_hl(), 0xff80);
+    }
+
+    #[test]
+    fn test_illegal_instruction_is_an_error() {
+        let mut cpu = cpu_with_program(&[0x00, 0xd3]);
+        cpu.step().unwrap();
+        assert_eq!(
+            cpu.step(),
+            Err(EmulationError::IllegalInstruction { pc: 1, opcode: 0xd3 })
+        );
+        assert_eq!(cpu.pc, 1);
+    }
+
+    #[test]
+    fn test_unmapped_access_reports_context() {
+        // LD A,(0xff10) reads an unmapped IO register
+        let mut cpu = cpu_with_program(&[0xfa, 0x10, 0xff, 0x00]);
+        cpu.set_access_mode(AccessMode::Strict);
+        assert_eq!(
+            cpu.step(),
+            Err(EmulationError::MemoryAccess {
+                pc: 0,
+                opcode: Some(0xfa),
+                error: BusError::UnmappedRead { address: 0xff10 },
+            })
+        );
+        assert_eq!(cpu.registers.a, 0xff);
+        assert_eq!(cpu.pc, 3);
+
+        cpu.set_access_mode(AccessMode::Lenient);
+        cpu.pc = 0;
+        assert_eq!(cpu.step(), Ok(16));
+    }
+
+    #[test]
+    fn test_timer_sees_writes_on_their_m_cycle() {
+        // LD (HL),A writes TIMA in its second M-cycle. Overflow happens on the fourth M-cycle,
+        // a write then cancels the reload while one an M-cycle later is lost to it
+        for &(nops, counter, interrupt) in [(2, 0x12, false), (3, 0xab, true)].iter() {
+            let mut program = vec![0x00; nops];
+            program.push(0x77);
+            let mut cpu = cpu_with_program(&program);
+            cpu.registers.a = 0x12;
+            cpu.registers.set_hl(0xff05);
+            cpu.bus.reset_divider();
+            cpu.bus.write_byte(0xff07, 0b101);
+            cpu.bus.write_byte(0xff06, 0xab);
+            cpu.bus.write_byte(0xff05, 0xff);
+            for _ in 0..=nops {
+                cpu.step().unwrap();
+            }
+            assert_eq!(cpu.bus.read_byte(0xff05), counter, "{} NOPs", nops);
+            let timer_requested = cpu.bus.read_byte(0xff0f) & Interrupt::Timer.mask() != 0;
+            assert_eq!(timer_requested, interrupt, "{} NOPs", nops);
+        }
+    }
+
+    #[test]
+    fn test_oam_dma_locks_bus_after_startup_m_cycle() {
+        // Runs from WRAM so the lock shows up in the fetches as well as the data accesses
+        let mut cpu = cpu_with_program(&[]);
+        for (offset, &byte) in [0xe0, 0x46, 0x7e].iter().enumerate() {
+            cpu.bus.write_byte(0xc000 + offset as u16, byte);
+        }
+        cpu.bus.write_byte(0xc100, 0x5a);
+        cpu.pc = 0xc000;
+        cpu.registers.a = 0xc1;
+        cpu.registers.set_hl(0xc100);
+
+        // LDH (0x46),A writes on its third M-cycle
+        assert_eq!(cpu.step(), Ok(12));
+        // The fetch of LD A,(HL) is on the startup M-cycle and still gets through, its read
+        // one M-cycle later is the first one locked out
+        assert_eq!(cpu.step(), Ok(8));
+        assert_eq!(cpu.pc, 0xc003);
+        assert_eq!(cpu.registers.a, 0xff);
+    }
+
+    // Column of line 0 where BGP changes when a store from WRAM writes it on the M-cycle ending
+    // at the given dot of the line, using either LDH (n),A or LD (HL),A
+    fn palette_switch_column(write_dot: usize, use_ldh: bool) -> usize {
+        let config = Config { ppu_backend: PpuBackend::PixelFifo, ..Config::default() };
+        let rom = vec![0; 0x8000];
+        let mut cpu = CPU::with_config(config, None, rom, Box::new(SystemClock)).unwrap();
+        // The store followed by JR -2
+        let program: &[u8] = if use_ldh { &[0xe0, 0x47, 0x18, 0xfe] } else { &[0x77, 0x18, 0xfe] };
+        for (offset, &byte) in program.iter().enumerate() {
+            cpu.bus.write_byte(0xc000 + offset as u16, byte);
+        }
+        cpu.pc = 0xc000;
+        cpu.registers.a = 0x03;
+        cpu.registers.set_hl(0xff47);
+
+        // Dot by dot to the start of VBlank, then on to the start of line 0
+        while cpu.bus.poll_frame().is_none() {
+            cpu.bus.step(1);
+        }
+        let vblank_dots = (LINES_PER_FRAME as usize - SCREEN_HEIGHT) * DOTS_PER_LINE as usize;
+        // LDH (n),A writes at the end of its third M-cycle and LD (HL),A at the end of its second
+        let write_cycles = if use_ldh { 12 } else { 8 };
+        for _ in 0..vblank_dots + write_dot - write_cycles {
+            cpu.bus.step(1);
+        }
+        loop {
+            // Colour 0 is shade 0 under the post-boot palette and shade 3 under the new one
+            if let Some(frame) = cpu.poll_frame() {
+                return (0..SCREEN_WIDTH).find(|&x| frame.shade(x, 0) == 3).unwrap();
+            }
+            cpu.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_palette_write_lands_on_its_m_cycle() {
+        // Both stores write on the M-cycle ending 40 dots into mode 3, so the palette changes at
+        // the same pixel
+        let write_dot = OAM_SCAN_DOTS as usize + 40;
+        let column = palette_switch_column(write_dot, true);
+        assert!(column > 0 && column < SCREEN_WIDTH - 4, "column {}", column);
+        assert_eq!(palette_switch_column(write_dot, false), column);
+        // One M-cycle later is four pixels further along
+        assert_eq!(palette_switch_column(write_dot + 4, true), column + 4);
+        assert_eq!(palette_switch_column(write_dot + 4, false), column + 4);
     }
 }
@@ -1,5 +1,8 @@
 use super::flags_register::FlagsRegister;
+use crate::cartridge::header::{CartridgeHeader, CgbSupport};
+use crate::model::Model;
 
+#[derive(Default)]
 pub struct Registers {
     pub a: u8,
     pub b: u8,
@@ -24,6 +27,50 @@ impl Registers {
             l: 0,
         }
     }
+    // What each model's boot ROM leaves behind. The DMG and MGB set H and C from their header
+    // checksum pass, CGB and AGB pick different values for games that use CGB features. For
+    // other games B and HL are left over from choosing a colour palette for them
+    pub fn post_boot(model: Model, header: &CartridgeHeader) -> Registers {
+        let checksum_flags = header.header_checksum != 0;
+        let cgb_game = header.cgb_support != CgbSupport::None;
+        // Only Nintendo's own games get a palette picked from their title
+        let title_checksum = if header.licensee.is_nintendo() { header.title_checksum } else { 0 };
+        let palette_hl = match title_checksum {
+            0x43 | 0x58 => 0x991A,
+            _ => 0x007C,
+        };
+        let (a, b, c, d, e, h, l) = match model {
+            Model::DMG0 => (0x01, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03),
+            Model::DMG => (0x01, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            Model::MGB => (0xFF, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            Model::SGB => (0x01, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            Model::SGB2 => (0xFF, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            Model::CGB | Model::AGB if cgb_game => (0x11, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D),
+            Model::CGB | Model::AGB => {
+                let (h, l) = ((palette_hl >> 8) as u8, palette_hl as u8);
+                (0x11, title_checksum, 0x00, 0x00, 0x08, h, l)
+            }
+        };
+        let mut registers = Registers { a, b, c, d, e, f: FlagsRegister::new(), h, l };
+        match model {
+            Model::DMG | Model::MGB => {
+                registers.f.zero = true;
+                registers.f.half_carry = checksum_flags;
+                registers.f.carry = checksum_flags;
+            }
+            Model::CGB => registers.f.zero = true,
+            // The AGB boot ROM ends with an extra INC B, which is how games detect it
+            Model::AGB => {
+                registers.f.half_carry = registers.b & 0x0F == 0x0F;
+                registers.b = registers.b.wrapping_add(1);
+                registers.f.zero = registers.b == 0;
+                registers.f.subtract = false;
+            }
+            _ => {}
+        }
+        registers
+    }
+
     pub fn get_af(&self) -> u16 {
         (self.a as u16) << 8 | u8::from(self.f) as u16
     }
@@ -75,6 +122,74 @@ mod tests {
         assert_eq!(test_af, registers.get_af());        
     }
 
+    fn header(checksum: u8, cgb_flag: u8) -> CartridgeHeader {
+        let mut rom = vec![0; 0x150];
+        rom[0x0143] = cgb_flag;
+        rom[0x014D] = checksum;
+        CartridgeHeader::parse(&rom).unwrap()
+    }
+
+    #[test]
+    fn test_post_boot() {
+        let registers = Registers::post_boot(Model::DMG, &header(0x42, 0x00));
+        assert_eq!(registers.get_af(), 0x01B0);
+        assert_eq!(registers.get_bc(), 0x0013);
+        assert_eq!(registers.get_de(), 0x00D8);
+        assert_eq!(registers.get_hl(), 0x014D);
+        assert_eq!(Registers::post_boot(Model::DMG, &header(0x00, 0x00)).get_af(), 0x0180);
+        assert_eq!(Registers::post_boot(Model::MGB, &header(0x42, 0x00)).a, 0xFF);
+        assert_eq!(Registers::post_boot(Model::SGB, &header(0x42, 0x00)).get_af(), 0x0100);
+    }
+
+    #[test]
+    fn test_post_boot_cgb_and_agb() {
+        let cgb = Registers::post_boot(Model::CGB, &header(0x42, 0x80));
+        assert_eq!(cgb.get_af(), 0x1180);
+        assert_eq!(cgb.get_de(), 0xFF56);
+        assert_eq!(Registers::post_boot(Model::CGB, &header(0x42, 0x00)).get_hl(), 0x007C);
+
+        let agb = Registers::post_boot(Model::AGB, &header(0x42, 0x80));
+        assert_eq!(agb.get_af(), 0x1100);
+        assert_eq!(agb.get_bc(), 0x0100);
+    }
+
+    #[test]
+    fn test_post_boot_cgb_with_nintendo_dmg_game() {
+        let mut rom = vec![0; 0x150];
+        rom[0x0134] = b'X';
+        rom[0x014B] = 0x01;
+        let nintendo = CartridgeHeader::parse(&rom).unwrap();
+        let cgb = Registers::post_boot(Model::CGB, &nintendo);
+        assert_eq!(cgb.get_bc(), 0x5800);
+        assert_eq!(cgb.get_hl(), 0x991A);
+        let agb = Registers::post_boot(Model::AGB, &nintendo);
+        assert_eq!(agb.get_bc(), 0x5900);
+        assert_eq!(agb.get_hl(), 0x991A);
+
+        // An odd checksum still goes up by one, and 0xFF wraps with INC's flags
+        rom[0x0134] = b'C';
+        let agb = Registers::post_boot(Model::AGB, &CartridgeHeader::parse(&rom).unwrap());
+        assert_eq!(agb.get_bc(), 0x4400);
+        assert_eq!(agb.get_af(), 0x1100);
+        assert_eq!(agb.get_hl(), 0x991A);
+        rom[0x0134] = 0xFF;
+        let agb = Registers::post_boot(Model::AGB, &CartridgeHeader::parse(&rom).unwrap());
+        assert_eq!(agb.get_bc(), 0x0000);
+        assert_eq!(agb.get_af(), 0x11A0);
+
+        rom[0x0134] = b'T';
+        let cgb = Registers::post_boot(Model::CGB, &CartridgeHeader::parse(&rom).unwrap());
+        assert_eq!(cgb.get_bc(), 0x5400);
+        assert_eq!(cgb.get_hl(), 0x007C);
+
+        // Other licensees don't get a palette
+        rom[0x0134] = b'X';
+        rom[0x014B] = 0x08;
+        let cgb = Registers::post_boot(Model::CGB, &CartridgeHeader::parse(&rom).unwrap());
+        assert_eq!(cgb.get_bc(), 0x0000);
+        assert_eq!(cgb.get_hl(), 0x007C);
+    }
+
     #[test]
     fn test_bc() {
         let mut registers = Registers::new();
@@ -0,0 +1,124 @@
+use crate::memory_bus::{ECHO_RAM_BEGIN, INTERNAL_RAM_BEGIN, OAM_BEGIN, OAM_SIZE};
+
+// The M-cycle after the write to 0xFF46 is spent setting up
+const STARTUP_M_CYCLES: u8 = 1;
+
+#[derive(Debug, Clone, Copy)]
+struct Transfer {
+    source: u16,
+    copied: u16,
+}
+
+// Copies a 160 byte page into OAM, one byte per M-cycle
+pub struct OamDma {
+    register: u8,
+    transfer: Option<Transfer>,
+    // A new transfer waiting out its startup delay. Any transfer already running keeps going
+    // until this one takes over
+    starting: Option<(u16, u8)>,
+}
+
+impl Default for OamDma {
+    fn default() -> OamDma {
+        OamDma::new()
+    }
+}
+
+impl OamDma {
+    pub fn new() -> OamDma {
+        OamDma {
+            register: 0,
+            transfer: None,
+            starting: None,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn start(&mut self, page: u8) {
+        self.register = page;
+        let mut source = (page as u16) << 8;
+        // Pages past WRAM read the echo, including 0xFE and 0xFF
+        if source as usize >= ECHO_RAM_BEGIN {
+            source -= (ECHO_RAM_BEGIN - INTERNAL_RAM_BEGIN) as u16;
+        }
+        self.starting = Some((source, STARTUP_M_CYCLES));
+    }
+
+    // Whether a transfer holds the bus, which locks the CPU out of most memory
+    pub fn is_active(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    // Runs one M-cycle, called before the CPU's access in it. Returns the source and OAM
+    // address of the byte to copy in it
+    pub fn tick(&mut self) -> Option<(u16, u16)> {
+        // The bus stays locked through the M-cycle of the last copy and is free on the next
+        if self.transfer.is_some_and(|transfer| transfer.copied as usize == OAM_SIZE) {
+            self.transfer = None;
+        }
+
+        if let Some((source, delay)) = self.starting {
+            if delay == 0 {
+                self.starting = None;
+                self.transfer = Some(Transfer { source, copied: 0 });
+            } else {
+                self.starting = Some((source, delay - 1));
+            }
+        }
+
+        self.transfer.as_mut().map(|transfer| {
+            let offset = transfer.copied;
+            transfer.copied += 1;
+            (transfer.source + offset, OAM_BEGIN as u16 + offset)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copies_160_bytes_after_startup() {
+        let mut dma = OamDma::new();
+        dma.start(0xC1);
+        assert_eq!(dma.read(), 0xC1);
+        // The startup M-cycle leaves the bus to the CPU
+        assert_eq!(dma.tick(), None);
+        assert!(!dma.is_active());
+        assert_eq!(dma.tick(), Some((0xC100, 0xFE00)));
+        assert!(dma.is_active());
+        for _ in 1..159 {
+            dma.tick();
+        }
+        assert_eq!(dma.tick(), Some((0xC19F, 0xFE9F)));
+        assert!(dma.is_active());
+        assert_eq!(dma.tick(), None);
+        assert!(!dma.is_active());
+    }
+
+    #[test]
+    fn test_high_pages_read_echo_ram() {
+        let mut dma = OamDma::new();
+        dma.start(0xFE);
+        dma.tick();
+        assert_eq!(dma.tick(), Some((0xDE00, 0xFE00)));
+    }
+
+    #[test]
+    fn test_restart_takes_over_after_its_own_delay() {
+        let mut dma = OamDma::new();
+        dma.start(0xC0);
+        for _ in 0..11 {
+            dma.tick();
+        }
+        dma.start(0xD0);
+        // The old transfer still owns this M-cycle
+        assert_eq!(dma.tick(), Some((0xC00A, 0xFE0A)));
+        assert!(dma.is_active());
+        assert_eq!(dma.tick(), Some((0xD000, 0xFE00)));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::cartridge::header::Mapper;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BusError {
+    UnmappedRead { address: u16 },
+    UnmappedWrite { address: u16, value: u8 },
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BusError::UnmappedRead { address } => {
+                write!(f, "read from unmapped address 0x{:04x}", address)
+            }
+            BusError::UnmappedWrite { address, value } => {
+                write!(f, "write of 0x{:02x} to unmapped address 0x{:04x}", value, address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmulationError {
+    BootRomSize { actual: usize, expected: usize },
+    GameRomTooSmall { actual: usize, minimum: usize },
+    UnsupportedCartridge { mapper: Mapper },
+    SaveDataSize { actual: usize, expected: usize },
+    UnknownInstruction { pc: u16, opcode: u8, prefixed: bool },
+    IllegalInstruction { pc: u16, opcode: u8 },
+    // The opcode is None when the access came from interrupt dispatch rather than an instruction
+    MemoryAccess { pc: u16, opcode: Option<u8>, error: BusError },
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulationError::BootRomSize { actual, expected } => write!(
+                f,
+                "boot ROM is the wrong size, is {} bytes but should be {} bytes",
+                actual, expected
+            ),
+            EmulationError::GameRomTooSmall { actual, minimum } => write!(
+                f,
+                "game ROM is {} bytes but must be at least {} bytes",
+                actual, minimum
+            ),
+            EmulationError::UnsupportedCartridge { mapper } => {
+                write!(f, "cartridges using {:?} are not supported", mapper)
+            }
+            EmulationError::SaveDataSize { actual, expected } => write!(
+                f,
+                "save data is {} bytes but the cartridge expects {} bytes",
+                actual, expected
+            ),
+            EmulationError::UnknownInstruction { pc, opcode, prefixed } => write!(
+                f,
+                "unknown instruction 0x{}{:02x} at 0x{:04x}",
+                if *prefixed { "cb" } else { "" },
+                opcode,
+                pc
+            ),
+            EmulationError::IllegalInstruction { pc, opcode } => {
+                write!(f, "illegal instruction 0x{:02x} at 0x{:04x}", opcode, pc)
+            }
+            EmulationError::MemoryAccess { pc, opcode: Some(opcode), error } => write!(
+                f,
+                "{} by instruction 0x{:02x} at 0x{:04x}",
+                error, opcode, pc
+            ),
+            EmulationError::MemoryAccess { pc, opcode: None, error } => {
+                write!(f, "{} during interrupt dispatch at 0x{:04x}", error, pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmulationError::MemoryAccess { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::memory_bus::{
+    JOYPAD_VECTOR, LCDSTAT_VECTOR, SERIAL_VECTOR, TIMER_VECTOR, VBLANK_VECTOR,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    VBlank,
+    LcdStat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+// In order of priority, the first pending one is serviced
+pub const INTERRUPTS: [Interrupt; 5] = [
+    Interrupt::VBlank,
+    Interrupt::LcdStat,
+    Interrupt::Timer,
+    Interrupt::Serial,
+    Interrupt::Joypad,
+];
+
+impl Interrupt {
+    pub fn vector(self) -> u16 {
+        match self {
+            Interrupt::VBlank => VBLANK_VECTOR,
+            Interrupt::LcdStat => LCDSTAT_VECTOR,
+            Interrupt::Timer => TIMER_VECTOR,
+            Interrupt::Serial => SERIAL_VECTOR,
+            Interrupt::Joypad => JOYPAD_VECTOR,
+        }
+    }
+
+    // The bit this interrupt occupies in both IF and IE
+    pub fn mask(self) -> u8 {
+        match self {
+            Interrupt::VBlank => 0b0000_0001,
+            Interrupt::LcdStat => 0b0000_0010,
+            Interrupt::Timer => 0b0000_0100,
+            Interrupt::Serial => 0b0000_1000,
+            Interrupt::Joypad => 0b0001_0000,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_are_distinct_and_in_priority_order() {
+        let mut previous = 0;
+        for interrupt in INTERRUPTS.iter() {
+            assert!(interrupt.mask() > previous);
+            previous = interrupt.mask();
+        }
+        assert_eq!(previous, 0b0001_0000);
+    }
+}
@@ -0,0 +1,171 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    // Bit in our pressed mask. Directions sit in the low nibble, the rest in the high one, both
+    // in the order P1 reports them
+    fn mask(self) -> u8 {
+        match self {
+            Button::Right => 1 << 0,
+            Button::Left => 1 << 1,
+            Button::Up => 1 << 2,
+            Button::Down => 1 << 3,
+            Button::A => 1 << 4,
+            Button::B => 1 << 5,
+            Button::Select => 1 << 6,
+            Button::Start => 1 << 7,
+        }
+    }
+
+    fn opposite(self) -> Option<Button> {
+        match self {
+            Button::Right => Some(Button::Left),
+            Button::Left => Some(Button::Right),
+            Button::Up => Some(Button::Down),
+            Button::Down => Some(Button::Up),
+            _ => None,
+        }
+    }
+}
+
+// Writing a 0 to one of these selects that group
+const SELECT_DIRECTIONS: u8 = 1 << 4;
+const SELECT_BUTTONS: u8 = 1 << 5;
+const SELECT_BITS: u8 = SELECT_DIRECTIONS | SELECT_BUTTONS;
+const UNUSED_BITS: u8 = 0b1100_0000;
+
+pub struct Joypad {
+    // Buttons the host is holding
+    held: u8,
+    // What the game sees, differs from held only when opposing directions are filtered
+    pressed: u8,
+    select: u8,
+    // A real d-pad can't press left and right together. Some games misbehave if they see it
+    filter_opposing_directions: bool,
+}
+
+impl Joypad {
+    pub fn new(filter_opposing_directions: bool) -> Joypad {
+        Joypad {
+            held: 0,
+            pressed: 0,
+            select: 0,
+            filter_opposing_directions,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        UNUSED_BITS | self.select | self.input_lines()
+    }
+
+    // Returns whether the write pulled an input line low, which requests the joypad interrupt
+    pub fn write(&mut self, value: u8) -> bool {
+        let before = self.input_lines();
+        self.select = value & SELECT_BITS;
+        falling_edge(before, self.input_lines())
+    }
+
+    // Returns whether the press should request the joypad interrupt
+    pub fn press(&mut self, button: Button) -> bool {
+        let before = self.input_lines();
+        self.held |= button.mask();
+        self.pressed |= button.mask();
+        if self.filter_opposing_directions {
+            // The most recent direction wins until it is released
+            if let Some(opposite) = button.opposite() {
+                self.pressed &= !opposite.mask();
+            }
+        }
+        falling_edge(before, self.input_lines())
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.held &= !button.mask();
+        self.pressed &= !button.mask();
+        if let Some(opposite) = button.opposite() {
+            self.pressed |= self.held & opposite.mask();
+        }
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.mask() != 0
+    }
+
+    // The four active low lines P10-P13 for whichever groups are selected
+    fn input_lines(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pressed |= self.pressed & 0x0F;
+        }
+        if self.select & SELECT_BUTTONS == 0 {
+            pressed |= self.pressed >> 4;
+        }
+        !pressed & 0x0F
+    }
+}
+
+fn falling_edge(before: u8, after: u8) -> bool {
+    before & !after != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_lines_pick_a_group() {
+        let mut joypad = Joypad::new(false);
+        joypad.press(Button::Start);
+        joypad.press(Button::Left);
+        joypad.write(0x20);
+        assert_eq!(joypad.read(), 0xED);
+        joypad.write(0x10);
+        assert_eq!(joypad.read(), 0xD7);
+        joypad.write(0x30);
+        assert_eq!(joypad.read(), 0xFF);
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0xC5);
+    }
+
+    #[test]
+    fn test_interrupt_on_falling_edge_only() {
+        let mut joypad = Joypad::new(false);
+        joypad.write(0x10);
+        // Directions aren't selected, so their line doesn't move
+        assert!(!joypad.press(Button::Up));
+        assert!(joypad.press(Button::A));
+        // Each button has its own line
+        assert!(joypad.press(Button::B));
+        joypad.release(Button::A);
+        joypad.release(Button::B);
+        // Selecting a group with a button already held pulls its line low too
+        assert!(joypad.write(0x20));
+    }
+
+    #[test]
+    fn test_opposing_directions() {
+        let mut joypad = Joypad::new(false);
+        joypad.press(Button::Left);
+        joypad.press(Button::Right);
+        assert!(joypad.is_pressed(Button::Left) && joypad.is_pressed(Button::Right));
+
+        let mut joypad = Joypad::new(true);
+        joypad.press(Button::Left);
+        joypad.press(Button::Right);
+        assert!(!joypad.is_pressed(Button::Left));
+        assert!(joypad.is_pressed(Button::Right));
+        joypad.release(Button::Right);
+        assert!(joypad.is_pressed(Button::Left));
+        joypad.press(Button::Up);
+        assert!(joypad.is_pressed(Button::Up));
+    }
+}
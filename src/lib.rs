@@ -1,2 +1,12 @@
+pub mod cartridge;
+pub mod config;
 pub mod cpu;
+pub mod dma;
+pub mod error;
+pub mod interrupts;
+pub mod joypad;
 pub mod memory_bus;
+pub mod model;
+pub mod ppu;
+pub mod serial;
+pub mod timer;
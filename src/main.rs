@@ -1,18 +1,80 @@
 use lib_rust_boi::cpu::CPU;
 
 use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Roughly one second of emulated time between writes of the .sav file
+const SAVE_INTERVAL_CYCLES: u64 = 4_194_304;
 
 pub fn main() {
     let boot_buffer = Some(buffer_from_file("./test_roms/dmg_boot.bin"));
-    let game_buffer = buffer_from_file("./test_roms/tetris.gb");
+    let game_path = Path::new("./test_roms/tetris.gb");
+    let game_buffer = buffer_from_file(game_path.to_str().unwrap());
+    let save_path = game_path.with_extension("sav");
     
-    let mut cpu = CPU::new(boot_buffer, game_buffer);
-    loop {
-        cpu.step();
+    let mut cpu = match CPU::new(boot_buffer, game_buffer) {
+        Ok(cpu) => cpu,
+        Err(error) => {
+            eprintln!("Could not start emulation: {}", error);
+            std::process::exit(1);
+        }
+    };
+    let header = cpu.cartridge_header();
+    if !header.validation.is_bootable() {
+        eprintln!("Warning: {} has a bad logo or header checksum, real hardware would refuse it", header.title);
     }
-}
 
+    if let Ok(save) = std::fs::read(&save_path) {
+        if let Err(error) = cpu.load_save_data(&save) {
+            eprintln!("Ignoring {}: {}", save_path.display(), error);
+        }
+    }
+    let mut last_ram = cpu.battery_ram().map(|ram| ram.to_vec());
+
+    // Ctrl-C and termination requests end the loop so the save gets flushed on the way out
+    let quit = Arc::new(AtomicBool::new(false));
+    let quit_handler = quit.clone();
+    if let Err(error) = ctrlc::set_handler(move || quit_handler.store(true, Ordering::SeqCst)) {
+        eprintln!("Could not install the quit handler, saves are only written periodically: {}", error);
+    }
 
+    let mut cycles_since_save: u64 = 0;
+    while !quit.load(Ordering::SeqCst) {
+        match cpu.step() {
+            Ok(cycles) => cycles_since_save += cycles as u64,
+            Err(error) => {
+                eprintln!("Emulation stopped: {}", error);
+                flush_save(&mut cpu, &save_path, &mut last_ram, true);
+                std::process::exit(1);
+            }
+        }
+        if cycles_since_save >= SAVE_INTERVAL_CYCLES {
+            cycles_since_save = 0;
+            flush_save(&mut cpu, &save_path, &mut last_ram, false);
+        }
+    }
+    flush_save(&mut cpu, &save_path, &mut last_ram, true);
+}
+
+// Writes the battery backed state out if the RAM changed since the last write, or always when
+// forced. The RTC footer changes every second on its own, so it alone never triggers a write
+fn flush_save(cpu: &mut CPU, save_path: &Path, last_ram: &mut Option<Vec<u8>>, force: bool) {
+    let ram = match cpu.battery_ram() {
+        Some(ram) => ram.to_vec(),
+        None => return,
+    };
+    if !force && last_ram.as_deref() == Some(&ram[..]) {
+        return;
+    }
+    let save = cpu.save_data().unwrap();
+    if let Err(error) = std::fs::write(save_path, save) {
+        eprintln!("Could not write {}: {}", save_path.display(), error);
+        return;
+    }
+    *last_ram = Some(ram);
+}
 
 fn buffer_from_file(path: &str) -> Vec<u8> {
     let mut file = std::fs::File::open(path).expect("File not there");
@@ -1,7 +1,28 @@
+use crate::cartridge::header::CartridgeHeader;
+use crate::cartridge::rtc::{Clock, SystemClock};
+use crate::cartridge::{self, Cartridge, CartridgeEvent};
+use crate::config::Config;
+use crate::dma::OamDma;
+use crate::error::{BusError, EmulationError};
+use crate::interrupts::{Interrupt, INTERRUPTS};
+use crate::joypad::{Button, Joypad};
+use crate::model::Model;
+use crate::ppu::framebuffer::Framebuffer;
+use crate::ppu::Ppu;
+use crate::serial::{Serial, SerialPeer};
+use crate::timer::Timer;
+
+use std::cell::Cell;
+
 pub const BOOT_ROM_BEGIN: usize = 0x00;
 pub const BOOT_ROM_END: usize = 0xFF;
 pub const BOOT_ROM_SIZE: usize = BOOT_ROM_END - BOOT_ROM_BEGIN + 1;
 
+// The CGB boot ROM skips over the cartridge header at 0x0100-0x01FF
+pub const CGB_BOOT_ROM_BEGIN: usize = 0x0200;
+pub const CGB_BOOT_ROM_END: usize = 0x08FF;
+pub const CGB_BOOT_ROM_SIZE: usize = CGB_BOOT_ROM_END + 1;
+
 pub const ROM_BANK_0_BEGIN: usize = 0x0000;
 pub const ROM_BANK_0_END: usize = 0x3FFF;
 pub const ROM_BANK_0_SIZE: usize = ROM_BANK_0_END - ROM_BANK_0_BEGIN + 1;
@@ -32,6 +53,29 @@ pub const OAM_SIZE: usize = OAM_END - OAM_BEGIN + 1;
 pub const UNUSED_BEGIN: usize = 0xFEA0;
 pub const UNUSED_END: usize = 0xFEFF;
 
+pub const JOYPAD_REGISTER: usize = 0xFF00;
+pub const SERIAL_DATA_REGISTER: usize = 0xFF01;
+pub const SERIAL_CONTROL_REGISTER: usize = 0xFF02;
+pub const DIVIDER_REGISTER: usize = 0xFF04;
+pub const TIMER_COUNTER_REGISTER: usize = 0xFF05;
+pub const TIMER_MODULO_REGISTER: usize = 0xFF06;
+pub const TIMER_CONTROL_REGISTER: usize = 0xFF07;
+pub const INTERRUPT_FLAG_REGISTER: usize = 0xFF0F;
+pub const LCD_CONTROL_REGISTER: usize = 0xFF40;
+pub const LCD_STATUS_REGISTER: usize = 0xFF41;
+pub const SCROLL_Y_REGISTER: usize = 0xFF42;
+pub const SCROLL_X_REGISTER: usize = 0xFF43;
+pub const LCD_Y_REGISTER: usize = 0xFF44;
+pub const LCD_Y_COMPARE_REGISTER: usize = 0xFF45;
+pub const OAM_DMA_REGISTER: usize = 0xFF46;
+pub const BACKGROUND_PALETTE_REGISTER: usize = 0xFF47;
+pub const OBJECT_PALETTE_0_REGISTER: usize = 0xFF48;
+pub const OBJECT_PALETTE_1_REGISTER: usize = 0xFF49;
+pub const WINDOW_Y_REGISTER: usize = 0xFF4A;
+pub const WINDOW_X_REGISTER: usize = 0xFF4B;
+pub const SPEED_SWITCH_REGISTER: usize = 0xFF4D;
+pub const BOOT_ROM_DISABLE_REGISTER: usize = 0xFF50;
+
 pub const IO_REGISTERS_BEGIN: usize = 0xFF00;
 pub const IO_REGISTERS_END: usize = 0xFF7F;
 
@@ -44,118 +88,455 @@ pub const INTERRUPT_ENABLE_REGISTER: usize = 0xFFFF;
 pub const VBLANK_VECTOR: u16 = 0x40;
 pub const LCDSTAT_VECTOR: u16 = 0x48;
 pub const TIMER_VECTOR: u16 = 0x50;
+pub const SERIAL_VECTOR: u16 = 0x58;
+pub const JOYPAD_VECTOR: u16 = 0x60;
+
+// What the data bus floats to when nothing answers a read
+const OPEN_BUS_VALUE: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessMode {
+    // Unmapped accesses are remembered so CPU::step can report them, meant for debugging tools
+    Strict,
+    // Unmapped reads see open bus and unmapped writes are dropped, the default since real games
+    // touch registers that aren't emulated, like the sound ones
+    Lenient,
+}
+
+// Only the low 5 bits of IF exist, the rest always read back as 1
+const INTERRUPT_FLAG_UNUSED_BITS: u8 = 0b1110_0000;
+
+// IF as the boot ROM leaves it when it jumps to 0x0100, DIV depends on the model
+const POST_BOOT_INTERRUPT_FLAG: u8 = 0x01;
+
+// The IO registers as the boot ROM leaves them on every model. STAT and LY come from where the
+// PPU is when it hands over, and NR10-NR52 belong here once sound is emulated
+const POST_BOOT_IO_REGISTERS: [(u16, u8); 14] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF40, 0x91), // LCDC
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF47, 0xFC), // BGP
+    (0xFF48, 0xFF), // OBP0
+    (0xFF49, 0xFF), // OBP1
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+];
+
+// SC, the CGB boot ROM leaves the internal clock selected
+const POST_BOOT_SERIAL_CONTROL: u8 = 0x7E;
+const CGB_POST_BOOT_SERIAL_CONTROL: u8 = 0x7F;
 
 pub struct MemoryBus {
-    boot_rom: Option<[u8; BOOT_ROM_SIZE]>,
-    rom_bank_0: [u8; ROM_BANK_0_SIZE],
-    rom_bank_n: [u8; ROM_BANK_N_SIZE],
-    cartridge_ram: [u8; CARTRIDGE_RAM_SIZE],
+    model: Model,
+    cartridge_header: CartridgeHeader,
+    cartridge: Box<dyn Cartridge>,
+    boot_rom: Option<Vec<u8>>,
     internal_ram: [u8; INTERNAL_RAM_SIZE],
     zero_page: [u8; ZERO_PAGE_SIZE],
+    interrupt_enable: u8,
+    interrupt_flag: u8,
+    timer: Timer,
+    ppu: Ppu,
+    dma: OamDma,
+    joypad: Joypad,
+    serial: Serial,
+    double_speed: bool,
+    speed_switch_armed: bool,
+    access_mode: AccessMode,
+    // First unmapped access since the last call to take_fault
+    fault: Cell<Option<BusError>>,
 }
 
 impl MemoryBus {
-    pub fn new(boot_rom_buffer: Option<Vec<u8>>, game_rom: Vec<u8>) -> MemoryBus {
-        let boot_rom = boot_rom_buffer.map(|boot_rom_buffer| {
-            if boot_rom_buffer.len() != BOOT_ROM_SIZE {
-                panic!(
-                    "Supplied boot ROM is the wrong size. Is {} bytes but should be {} bytes",
-                    boot_rom_buffer.len(),
-                    BOOT_ROM_SIZE
-                );
-            }
-            let mut boot_rom = [0; BOOT_ROM_SIZE];
-            boot_rom.copy_from_slice(&boot_rom_buffer);
-            boot_rom
-        });
+    pub fn new(boot_rom: Option<Vec<u8>>, game_rom: Vec<u8>) -> Result<MemoryBus, EmulationError> {
+        MemoryBus::with_model(Model::default(), boot_rom, game_rom)
+    }
 
-        let mut rom_bank_0 = [0; ROM_BANK_0_SIZE];
-        for i in 0..ROM_BANK_0_SIZE {
-            rom_bank_0[i] = game_rom[i];
-        }
+    pub fn with_model(
+        model: Model,
+        boot_rom: Option<Vec<u8>>,
+        game_rom: Vec<u8>,
+    ) -> Result<MemoryBus, EmulationError> {
+        let config = Config { model, ..Config::default() };
+        MemoryBus::with_config(config, boot_rom, game_rom, Box::new(SystemClock))
+    }
 
-        let mut rom_bank_n = [0; ROM_BANK_N_SIZE];
-        for i in 0..ROM_BANK_N_SIZE {
-            rom_bank_n[i] = game_rom[ROM_BANK_0_SIZE + i];
+    // The clock drives any cartridge RTC, tests can pass one they control
+    pub fn with_config(
+        config: Config,
+        boot_rom: Option<Vec<u8>>,
+        game_rom: Vec<u8>,
+        clock: Box<dyn Clock>,
+    ) -> Result<MemoryBus, EmulationError> {
+        let model = config.model;
+        if let Some(boot_rom) = &boot_rom {
+            if boot_rom.len() != model.boot_rom_size() {
+                return Err(EmulationError::BootRomSize {
+                    actual: boot_rom.len(),
+                    expected: model.boot_rom_size(),
+                });
+            }
         }
-        MemoryBus {
+
+        let cartridge_header = match CartridgeHeader::parse(&game_rom) {
+            Some(header) if game_rom.len() >= ROM_BANK_0_SIZE + ROM_BANK_N_SIZE => header,
+            _ => {
+                return Err(EmulationError::GameRomTooSmall {
+                    actual: game_rom.len(),
+                    minimum: ROM_BANK_0_SIZE + ROM_BANK_N_SIZE,
+                });
+            }
+        };
+
+        let cartridge = cartridge::from_rom_with_clock(game_rom, &cartridge_header, clock)?;
+        let mut memory_bus = MemoryBus {
+            model,
+            cartridge_header,
+            cartridge,
             boot_rom,
-            rom_bank_0,
-            rom_bank_n,
-            cartridge_ram: [0; CARTRIDGE_RAM_SIZE],
             internal_ram: [0; INTERNAL_RAM_SIZE],
             zero_page: [0; ZERO_PAGE_SIZE],
+            interrupt_enable: 0,
+            interrupt_flag: 0,
+            timer: Timer::new(0),
+            ppu: Ppu::new(config.ppu_backend),
+            dma: OamDma::new(),
+            joypad: Joypad::new(config.filter_opposing_directions),
+            serial: Serial::new(model.is_cgb()),
+            double_speed: false,
+            speed_switch_armed: false,
+            access_mode: AccessMode::Lenient,
+            fault: Cell::new(None),
+        };
+        if memory_bus.boot_rom.is_none() {
+            memory_bus.apply_post_boot_state();
+        }
+        Ok(memory_bus)
+    }
+
+    fn apply_post_boot_state(&mut self) {
+        self.timer = Timer::new(self.model.post_boot_divider());
+        self.interrupt_flag = POST_BOOT_INTERRUPT_FLAG;
+        for &(address, value) in POST_BOOT_IO_REGISTERS.iter() {
+            self.write_byte(address, value);
+        }
+        let serial_control = if self.model.is_cgb() {
+            CGB_POST_BOOT_SERIAL_CONTROL
+        } else {
+            POST_BOOT_SERIAL_CONTROL
+        };
+        self.write_byte(SERIAL_CONTROL_REGISTER as u16, serial_control);
+        self.ppu.skip_boot(self.model.post_boot_line());
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn cartridge_header(&self) -> &CartridgeHeader {
+        &self.cartridge_header
+    }
+
+    // None unless the header says the cartridge has a battery to keep its RAM alive
+    pub fn save_data(&mut self) -> Option<Vec<u8>> {
+        if self.cartridge_header.cartridge_type.battery {
+            Some(self.cartridge.save_data())
+        } else {
+            None
+        }
+    }
+
+    // Just the battery backed RAM, without the RTC state save_data appends. Unlike the RTC it
+    // only changes when the game writes to it, so it tells the host when a save is worth writing
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        if self.cartridge_header.cartridge_type.battery {
+            Some(self.cartridge.ram())
+        } else {
+            None
+        }
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) -> Result<(), EmulationError> {
+        self.cartridge.load_save_data(data)
+    }
+
+    pub fn poll_cartridge_event(&mut self) -> Option<CartridgeEvent> {
+        self.cartridge.poll_event()
+    }
+
+    pub fn set_access_mode(&mut self, access_mode: AccessMode) {
+        self.access_mode = access_mode;
+        self.fault.set(None);
+    }
+
+    pub fn take_fault(&self) -> Option<BusError> {
+        self.fault.take()
+    }
+
+    fn record_fault(&self, error: BusError) {
+        if self.access_mode == AccessMode::Strict && self.fault.get().is_none() {
+            self.fault.set(Some(error));
         }
-        
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
+        self.try_read_byte(address).unwrap_or_else(|error| {
+            self.record_fault(error);
+            OPEN_BUS_VALUE
+        })
+    }
+
+    pub fn write_byte(&mut self, address: u16, byte: u8) {
+        if let Err(error) = self.try_write_byte(address, byte) {
+            self.record_fault(error);
+        }
+    }
+
+    pub fn try_read_byte(&self, address: u16) -> Result<u8, BusError> {
+        if self.blocked_by_dma(address) {
+            return Ok(OPEN_BUS_VALUE);
+        }
+        self.read_mapped(address)
+    }
+
+    pub fn try_write_byte(&mut self, address: u16, byte: u8) -> Result<(), BusError> {
+        if self.blocked_by_dma(address) {
+            return Ok(());
+        }
+        self.write_mapped(address, byte)
+    }
+
+    // While OAM DMA runs it owns the external and video buses, leaving the CPU with only the
+    // memory inside the chip: IO, HRAM and IE
+    fn blocked_by_dma(&self, address: u16) -> bool {
+        self.dma.is_active() && (address as usize) < IO_REGISTERS_BEGIN
+    }
+
+    fn read_mapped(&self, address: u16) -> Result<u8, BusError> {
         let address = address as usize;
-        match address {
-            BOOT_ROM_BEGIN..=BOOT_ROM_END =>
-            {
-                if let Some(boot_rom) = self.boot_rom {
-                    boot_rom[address]
-                }
-                else {
-                    self.rom_bank_0[address]
-                }
+        let value = match address {
+            BOOT_ROM_BEGIN..=BOOT_ROM_END if self.boot_rom.is_some() => {
+                self.boot_rom.as_ref().unwrap()[address]
+            }
+            CGB_BOOT_ROM_BEGIN..=CGB_BOOT_ROM_END if self.boot_rom_covers(address) => {
+                self.boot_rom.as_ref().unwrap()[address]
             }
-            ROM_BANK_0_BEGIN..=ROM_BANK_0_END => self.rom_bank_0[address],
-            ROM_BANK_N_BEGIN..=ROM_BANK_N_END => self.rom_bank_n[address - ROM_BANK_N_BEGIN],
-            //todo vram
-            CARTRIDGE_RAM_BEGIN..=CARTRIDGE_RAM_END => self.cartridge_ram[address - CARTRIDGE_RAM_BEGIN],
+            ROM_BANK_0_BEGIN..=ROM_BANK_N_END => self.cartridge.read_rom(address as u16),
+            VRAM_BEGIN..=VRAM_END => self.ppu.read_vram(address as u16),
+            CARTRIDGE_RAM_BEGIN..=CARTRIDGE_RAM_END => self.cartridge.read_ram(address as u16),
             INTERNAL_RAM_BEGIN..=INTERNAL_RAM_END => self.internal_ram[address - INTERNAL_RAM_BEGIN],
             ECHO_RAM_BEGIN..=ECHO_RAM_END => self.internal_ram[address - ECHO_RAM_BEGIN],
-            //todo oam
+            OAM_BEGIN..=OAM_END => self.ppu.read_oam(address as u16),
             UNUSED_BEGIN..=UNUSED_END => 0,
+            JOYPAD_REGISTER => self.joypad.read(),
+            SERIAL_DATA_REGISTER..=SERIAL_CONTROL_REGISTER => self.serial.read(address as u16),
+            DIVIDER_REGISTER..=TIMER_CONTROL_REGISTER => self.timer.read(address as u16),
+            INTERRUPT_FLAG_REGISTER => self.interrupt_flag | INTERRUPT_FLAG_UNUSED_BITS,
+            LCD_CONTROL_REGISTER
+            | LCD_STATUS_REGISTER
+            | SCROLL_Y_REGISTER
+            | SCROLL_X_REGISTER
+            | LCD_Y_REGISTER
+            | LCD_Y_COMPARE_REGISTER
+            | BACKGROUND_PALETTE_REGISTER
+            | OBJECT_PALETTE_0_REGISTER
+            | OBJECT_PALETTE_1_REGISTER
+            | WINDOW_Y_REGISTER
+            | WINDOW_X_REGISTER => self.ppu.read(address as u16),
+            SPEED_SWITCH_REGISTER if self.model.is_cgb() => {
+                0b0111_1110 | (self.double_speed as u8) << 7 | self.speed_switch_armed as u8
+            }
+            OAM_DMA_REGISTER => self.dma.read(),
+            // Write only, reads see nothing driving the bus
+            BOOT_ROM_DISABLE_REGISTER => OPEN_BUS_VALUE,
             ZERO_PAGE_BEGIN..=ZERO_PAGE_END => self.zero_page[address - ZERO_PAGE_BEGIN],
-            //todo interupt flag
+            INTERRUPT_ENABLE_REGISTER => self.interrupt_enable,
             _ => {
-                panic!(
-                    "Reading from an unkown part of memory at address 0x{:x}",
-                    address
-                );
+                return Err(BusError::UnmappedRead { address: address as u16 });
             }
-        }
+        };
+        Ok(value)
     }
-    pub fn write_byte(&mut self, address: u16, byte: u8) {
+
+    fn write_mapped(&mut self, address: u16, byte: u8) -> Result<(), BusError> {
         let address = address as usize;
         match address {
-            ROM_BANK_0_BEGIN..=ROM_BANK_0_END => {
-                self.rom_bank_0[address] = byte;
+            ROM_BANK_0_BEGIN..=ROM_BANK_N_END => {
+                self.cartridge.write_rom(address as u16, byte);
             },
             VRAM_BEGIN..=VRAM_END => {
-                //todo gpu
+                self.ppu.write_vram(address as u16, byte);
             },
             CARTRIDGE_RAM_BEGIN..=CARTRIDGE_RAM_END => {
-                self.cartridge_ram[address - CARTRIDGE_RAM_BEGIN] = byte;
+                self.cartridge.write_ram(address as u16, byte);
+            },
+            INTERNAL_RAM_BEGIN..=INTERNAL_RAM_END => {
+                self.internal_ram[address - INTERNAL_RAM_BEGIN] = byte;
+            },
+            ECHO_RAM_BEGIN..=ECHO_RAM_END => {
+                self.internal_ram[address - ECHO_RAM_BEGIN] = byte;
             },
             OAM_BEGIN..=OAM_END => {
-                //todo more gpu
+                self.ppu.write_oam(address as u16, byte);
             },
+            UNUSED_BEGIN..=UNUSED_END => {/*DO NOTHING*/},
             IO_REGISTERS_BEGIN..=IO_REGISTERS_END => {
-                //todo io
+                if let Some(interrupt) = self.write_io(address, byte) {
+                    self.request_interrupt(interrupt);
+                }
             },
-            UNUSED_BEGIN..=UNUSED_END => {/*DO NOTHING*/},
             ZERO_PAGE_BEGIN..=ZERO_PAGE_END => {
                 self.zero_page[address - ZERO_PAGE_BEGIN] = byte;
             },
             INTERRUPT_ENABLE_REGISTER => {
-                //todo interrupt reg
-                panic!("interrupt not set up yet!");
+                self.interrupt_enable = byte;
             },
             _ => {
-                panic!("Couldn't write to address 0x{:x} not a supported address", address);
+                return Err(BusError::UnmappedWrite { address: address as u16, value: byte });
+            },
+        }
+        Ok(())
+    }
+
+    // Returns the interrupt the write raised, if any
+    fn write_io(&mut self, address: usize, byte: u8) -> Option<Interrupt> {
+        match address {
+            JOYPAD_REGISTER => {
+                return self.joypad.write(byte).then_some(Interrupt::Joypad);
+            },
+            SERIAL_DATA_REGISTER..=SERIAL_CONTROL_REGISTER => {
+                self.serial.write(address as u16, byte);
+            },
+            DIVIDER_REGISTER..=TIMER_CONTROL_REGISTER => {
+                self.timer.write(address as u16, byte);
+            },
+            INTERRUPT_FLAG_REGISTER => {
+                self.interrupt_flag = byte & !INTERRUPT_FLAG_UNUSED_BITS;
             },
+            LCD_CONTROL_REGISTER
+            | LCD_STATUS_REGISTER
+            | SCROLL_Y_REGISTER
+            | SCROLL_X_REGISTER
+            | LCD_Y_REGISTER
+            | LCD_Y_COMPARE_REGISTER
+            | BACKGROUND_PALETTE_REGISTER
+            | OBJECT_PALETTE_0_REGISTER
+            | OBJECT_PALETTE_1_REGISTER
+            | WINDOW_Y_REGISTER
+            | WINDOW_X_REGISTER => {
+                return self.ppu.write(address as u16, byte).then_some(Interrupt::LcdStat);
+            },
+            OAM_DMA_REGISTER => {
+                self.dma.start(byte);
+            },
+            SPEED_SWITCH_REGISTER if self.model.is_cgb() => {
+                self.speed_switch_armed = byte & 0b1 != 0;
+            },
+            // Once unmapped the boot ROM can't come back until a reset
+            BOOT_ROM_DISABLE_REGISTER if byte != 0 => {
+                self.boot_rom = None;
+            },
+            _ => {
+                //todo io
+            },
+        }
+        None
+    }
+
+    fn boot_rom_covers(&self, address: usize) -> bool {
+        self.boot_rom.as_ref().is_some_and(|boot_rom| address < boot_rom.len())
+    }
+
+    // Advances everything on the bus by the number of cycles the CPU just spent
+    pub fn step(&mut self, cycles: u8) {
+        for _ in 0..cycles / 4 {
+            if let Some((source, destination)) = self.dma.tick() {
+                let byte = self.read_mapped(source).unwrap_or(OPEN_BUS_VALUE);
+                self.ppu.write_oam(destination, byte);
+            }
+        }
+        if self.timer.step(cycles) {
+            self.request_interrupt(Interrupt::Timer);
+        }
+        if self.serial.step(cycles) {
+            self.request_interrupt(Interrupt::Serial);
+        }
+        // The PPU doesn't speed up in double speed mode
+        let dots = if self.double_speed { cycles / 2 } else { cycles };
+        let interrupts = self.ppu.step(dots);
+        if interrupts.vblank {
+            self.request_interrupt(Interrupt::VBlank);
+        }
+        if interrupts.stat {
+            self.request_interrupt(Interrupt::LcdStat);
+        }
+    }
+
+    // Plugs something into the link port, replacing whatever was there
+    pub fn connect_serial(&mut self, peer: Box<dyn SerialPeer>) {
+        self.serial.connect(peer);
+    }
+
+    pub fn press_button(&mut self, button: Button) {
+        if self.joypad.press(button) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    pub fn release_button(&mut self, button: Button) {
+        self.joypad.release(button);
+    }
+
+    pub fn poll_frame(&mut self) -> Option<&Framebuffer> {
+        self.ppu.take_frame()
+    }
+
+    pub fn reset_divider(&mut self) {
+        self.timer.reset_divider();
+    }
+
+    // Performs a CGB speed switch if KEY1 was armed, returns whether one happened
+    pub fn switch_speed(&mut self) -> bool {
+        if !self.speed_switch_armed {
+            return false;
         }
+        self.speed_switch_armed = false;
+        self.double_speed = !self.double_speed;
+        true
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag |= interrupt.mask();
+    }
+
+    pub fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag &= !interrupt.mask();
+    }
+
+    // The highest priority interrupt that is both requested and enabled, regardless of IME
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        let pending = self.interrupt_flag & self.interrupt_enable;
+        INTERRUPTS
+            .iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+            .copied()
     }
 }
 
 #[test]
 fn test_write_cartridge_ram_begin() {
     let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
-    let mut memory_bus = MemoryBus::new(None, game_rom);
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
     let addr = CARTRIDGE_RAM_BEGIN as u16;
     let expected = 0xAB;
     memory_bus.write_byte(addr, expected);
@@ -166,7 +547,7 @@ fn test_write_cartridge_ram_begin() {
 #[test]
 fn test_write_cartridge_ram_middle() {
     let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
-    let mut memory_bus = MemoryBus::new(None, game_rom);
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
     let addr = CARTRIDGE_RAM_BEGIN as u16 + (CARTRIDGE_RAM_SIZE as u16 / 2);
     let expected = 0x34;
     memory_bus.write_byte(addr, expected);
@@ -177,7 +558,7 @@ fn test_write_cartridge_ram_middle() {
 #[test]
 fn test_write_cartridge_ram_end() {
     let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
-    let mut memory_bus = MemoryBus::new(None, game_rom);
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
     let addr = CARTRIDGE_RAM_END as u16;
     let expected = 0xBC;
     memory_bus.write_byte(addr, expected);
@@ -186,34 +567,344 @@ fn test_write_cartridge_ram_end() {
 }
 
 #[test]
-fn test_write_rom_bank_0_begin() {
+fn test_write_rom_bank_0_begin_leaves_rom_alone() {
     let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
-    let mut memory_bus = MemoryBus::new(None, game_rom);
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
     let addr = ROM_BANK_0_BEGIN as u16;
-    let expected = 0x42;
-    memory_bus.write_byte(addr, expected);
+    memory_bus.write_byte(addr, 0x42);
     let value = memory_bus.read_byte(addr);
-    assert_eq!(value, expected);
+    assert_eq!(value, 0);
 }
 
 #[test]
-fn test_write_rom_bank_0_middle() {
+fn test_write_rom_bank_0_middle_leaves_rom_alone() {
     let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
-    let mut memory_bus = MemoryBus::new(None, game_rom);
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
     let addr = (ROM_BANK_0_BEGIN + (ROM_BANK_0_SIZE / 2)) as u16;
-    let expected = 0x44;
-    memory_bus.write_byte(addr, expected);
+    memory_bus.write_byte(addr, 0x44);
     let value = memory_bus.read_byte(addr);
-    assert_eq!(value, expected);
+    assert_eq!(value, 0);
 }
 
 #[test]
-fn test_write_rom_bank_0_end() {
+fn test_write_rom_bank_0_end_leaves_rom_alone() {
     let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
-    let mut memory_bus = MemoryBus::new(None, game_rom);
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
     let addr = ROM_BANK_0_END as u16;
-    let expected = 0x45;
-    memory_bus.write_byte(addr, expected);
+    memory_bus.write_byte(addr, 0x45);
     let value = memory_bus.read_byte(addr);
-    assert_eq!(value, expected);
-}
\ No newline at end of file
+    assert_eq!(value, 0);
+}
+
+#[test]
+fn test_interrupt_flag_upper_bits_read_as_set() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
+    memory_bus.write_byte(INTERRUPT_FLAG_REGISTER as u16, 0xff);
+    assert_eq!(memory_bus.read_byte(INTERRUPT_FLAG_REGISTER as u16), 0xff);
+    memory_bus.write_byte(INTERRUPT_FLAG_REGISTER as u16, 0x00);
+    assert_eq!(memory_bus.read_byte(INTERRUPT_FLAG_REGISTER as u16), 0xe0);
+}
+
+#[test]
+fn test_pending_interrupt_priority() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
+    memory_bus.acknowledge_interrupt(Interrupt::VBlank);
+    memory_bus.request_interrupt(Interrupt::Joypad);
+    memory_bus.request_interrupt(Interrupt::Timer);
+    assert_eq!(memory_bus.pending_interrupt(), None);
+    memory_bus.write_byte(INTERRUPT_ENABLE_REGISTER as u16, 0xff);
+    assert_eq!(memory_bus.pending_interrupt(), Some(Interrupt::Timer));
+    memory_bus.acknowledge_interrupt(Interrupt::Timer);
+    assert_eq!(memory_bus.pending_interrupt(), Some(Interrupt::Joypad));
+}
+
+#[test]
+fn test_divider_counts_and_resets_on_write() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
+    memory_bus.reset_divider();
+    for _ in 0..64 {
+        memory_bus.step(8);
+    }
+    assert_eq!(memory_bus.read_byte(DIVIDER_REGISTER as u16), 2);
+    memory_bus.write_byte(DIVIDER_REGISTER as u16, 0x55);
+    assert_eq!(memory_bus.read_byte(DIVIDER_REGISTER as u16), 0);
+}
+
+#[test]
+fn test_speed_switch_only_when_armed() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::with_model(Model::CGB, None, game_rom).unwrap();
+    assert!(!memory_bus.switch_speed());
+    memory_bus.write_byte(SPEED_SWITCH_REGISTER as u16, 0x01);
+    assert_eq!(memory_bus.read_byte(SPEED_SWITCH_REGISTER as u16), 0x7f);
+    assert!(memory_bus.switch_speed());
+    assert!(memory_bus.is_double_speed());
+    assert_eq!(memory_bus.read_byte(SPEED_SWITCH_REGISTER as u16), 0xfe);
+}
+
+#[test]
+fn test_new_rejects_bad_rom_sizes() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    assert_eq!(
+        MemoryBus::new(Some(vec![0; 10]), game_rom).err(),
+        Some(EmulationError::BootRomSize { actual: 10, expected: BOOT_ROM_SIZE })
+    );
+    assert_eq!(
+        MemoryBus::new(None, vec![0; 0x100]).err(),
+        Some(EmulationError::GameRomTooSmall {
+            actual: 0x100,
+            minimum: ROM_BANK_0_SIZE + ROM_BANK_N_SIZE
+        })
+    );
+}
+
+#[test]
+fn test_unmapped_access_modes() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
+    assert_eq!(
+        memory_bus.try_read_byte(0xff10),
+        Err(BusError::UnmappedRead { address: 0xff10 })
+    );
+    // Lenient by default, so a sound register read is open bus rather than a fault
+    assert_eq!(memory_bus.read_byte(0xff26), OPEN_BUS_VALUE);
+    assert_eq!(memory_bus.take_fault(), None);
+
+    memory_bus.set_access_mode(AccessMode::Strict);
+    assert_eq!(memory_bus.read_byte(0xff10), OPEN_BUS_VALUE);
+    assert_eq!(memory_bus.take_fault(), Some(BusError::UnmappedRead { address: 0xff10 }));
+    assert_eq!(memory_bus.take_fault(), None);
+}
+
+#[test]
+fn test_mbc1_rom_banking_through_bus() {
+    let mut game_rom: Vec<u8> = vec![0; 8 * ROM_BANK_N_SIZE];
+    game_rom[0x0147] = 0x01;
+    game_rom[3 * ROM_BANK_N_SIZE] = 0x33;
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
+    memory_bus.write_byte(0x2000, 0x03);
+    assert_eq!(memory_bus.read_byte(ROM_BANK_N_BEGIN as u16), 0x33);
+    assert_eq!(memory_bus.take_fault(), None);
+}
+
+#[test]
+fn test_unsupported_mapper_is_an_error() {
+    let mut game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    game_rom[0x0147] = 0xFC;
+    assert_eq!(
+        MemoryBus::new(None, game_rom).err(),
+        Some(EmulationError::UnsupportedCartridge {
+            mapper: crate::cartridge::header::Mapper::PocketCamera
+        })
+    );
+}
+
+#[test]
+fn test_save_data_only_with_battery() {
+    let mut game_rom: Vec<u8> = vec![0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE];
+    game_rom[0x0147] = 0x01;
+    let mut memory_bus = MemoryBus::new(None, game_rom.clone()).unwrap();
+    assert_eq!(memory_bus.save_data(), None);
+    assert_eq!(memory_bus.battery_ram(), None);
+
+    game_rom[0x0147] = 0x03;
+    game_rom[0x0149] = 0x02;
+    let mut memory_bus = MemoryBus::new(None, game_rom).unwrap();
+    let mut save = vec![0; CARTRIDGE_RAM_SIZE];
+    save[0x10] = 0x42;
+    memory_bus.load_save_data(&save).unwrap();
+    memory_bus.write_byte(0x0000, 0x0A);
+    assert_eq!(memory_bus.read_byte(CARTRIDGE_RAM_BEGIN as u16 + 0x10), 0x42);
+    assert_eq!(memory_bus.battery_ram(), Some(&save[..]));
+    assert_eq!(memory_bus.save_data(), Some(save));
+}
+
+#[test]
+fn test_boot_rom_unmapped_by_ff50() {
+    let mut game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    game_rom[0x0000] = 0xC3;
+    let mut memory_bus = MemoryBus::new(Some(vec![0x31; BOOT_ROM_SIZE]), game_rom).unwrap();
+    assert_eq!(memory_bus.read_byte(0x0000), 0x31);
+    assert_eq!(memory_bus.read_byte(0x0100), 0x00);
+
+    memory_bus.write_byte(BOOT_ROM_DISABLE_REGISTER as u16, 0x00);
+    assert_eq!(memory_bus.read_byte(0x0000), 0x31);
+    memory_bus.write_byte(BOOT_ROM_DISABLE_REGISTER as u16, 0x01);
+    assert_eq!(memory_bus.read_byte(0x0000), 0xC3);
+    assert_eq!(memory_bus.take_fault(), None);
+}
+
+#[test]
+fn test_post_boot_io_without_boot_rom() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let memory_bus = MemoryBus::new(None, game_rom.clone()).unwrap();
+    assert_eq!(memory_bus.read_byte(DIVIDER_REGISTER as u16), 0xAB);
+    assert_eq!(memory_bus.read_byte(INTERRUPT_FLAG_REGISTER as u16), 0xE1);
+    assert_eq!(memory_bus.read_byte(JOYPAD_REGISTER as u16), 0xCF);
+    assert_eq!(memory_bus.read_byte(SERIAL_CONTROL_REGISTER as u16), 0x7E);
+    assert_eq!(memory_bus.read_byte(TIMER_CONTROL_REGISTER as u16), 0xF8);
+    assert_eq!(memory_bus.read_byte(LCD_STATUS_REGISTER as u16), 0x85);
+    assert_eq!(memory_bus.read_byte(LCD_Y_REGISTER as u16), 0x00);
+
+    let memory_bus = MemoryBus::with_model(Model::DMG0, None, game_rom.clone()).unwrap();
+    assert_eq!(memory_bus.read_byte(LCD_STATUS_REGISTER as u16), 0x81);
+    assert_eq!(memory_bus.read_byte(LCD_Y_REGISTER as u16), 0x91);
+
+    let memory_bus = MemoryBus::with_model(Model::CGB, None, game_rom.clone()).unwrap();
+    assert_eq!(memory_bus.read_byte(SERIAL_CONTROL_REGISTER as u16), 0x7F);
+
+    let memory_bus = MemoryBus::new(Some(vec![0; BOOT_ROM_SIZE]), game_rom).unwrap();
+    assert_eq!(memory_bus.read_byte(DIVIDER_REGISTER as u16), 0x00);
+    assert_eq!(memory_bus.read_byte(INTERRUPT_FLAG_REGISTER as u16), 0xE0);
+}
+
+#[test]
+fn test_cgb_boot_rom_leaves_header_visible() {
+    let mut game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    game_rom[0x0100] = 0x00;
+    game_rom[0x0900] = 0xC3;
+    let boot_rom = vec![0x31; CGB_BOOT_ROM_SIZE];
+    let memory_bus = MemoryBus::with_model(Model::CGB, Some(boot_rom), game_rom.clone()).unwrap();
+    assert_eq!(memory_bus.read_byte(0x00FF), 0x31);
+    assert_eq!(memory_bus.read_byte(0x0100), 0x00);
+    assert_eq!(memory_bus.read_byte(0x0200), 0x31);
+    assert_eq!(memory_bus.read_byte(0x08FF), 0x31);
+    assert_eq!(memory_bus.read_byte(0x0900), 0xC3);
+
+    assert_eq!(
+        MemoryBus::with_model(Model::CGB, Some(vec![0; BOOT_ROM_SIZE]), game_rom).err(),
+        Some(EmulationError::BootRomSize { actual: BOOT_ROM_SIZE, expected: CGB_BOOT_ROM_SIZE })
+    );
+}
+
+#[test]
+fn test_speed_switch_register_only_on_cgb() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::with_model(Model::DMG, None, game_rom).unwrap();
+    memory_bus.write_byte(SPEED_SWITCH_REGISTER as u16, 0x01);
+    assert!(!memory_bus.switch_speed());
+    assert!(memory_bus.try_read_byte(SPEED_SWITCH_REGISTER as u16).is_err());
+}
+
+#[test]
+fn test_timer_overflow_requests_interrupt() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(Some(vec![0; BOOT_ROM_SIZE]), game_rom).unwrap();
+    memory_bus.write_byte(INTERRUPT_ENABLE_REGISTER as u16, 0xff);
+    memory_bus.write_byte(TIMER_MODULO_REGISTER as u16, 0xf0);
+    memory_bus.write_byte(TIMER_COUNTER_REGISTER as u16, 0xff);
+    memory_bus.write_byte(TIMER_CONTROL_REGISTER as u16, 0b101);
+    memory_bus.step(16);
+    assert_eq!(memory_bus.pending_interrupt(), None);
+    memory_bus.step(4);
+    assert_eq!(memory_bus.pending_interrupt(), Some(Interrupt::Timer));
+    assert_eq!(memory_bus.read_byte(TIMER_COUNTER_REGISTER as u16), 0xf0);
+}
+
+#[test]
+fn test_vram_and_vblank_interrupt() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(Some(vec![0; BOOT_ROM_SIZE]), game_rom).unwrap();
+    memory_bus.write_byte(VRAM_BEGIN as u16, 0x42);
+    memory_bus.write_byte(VRAM_END as u16, 0x24);
+    assert_eq!(memory_bus.read_byte(VRAM_BEGIN as u16), 0x42);
+    assert_eq!(memory_bus.read_byte(VRAM_END as u16), 0x24);
+    memory_bus.write_byte(OAM_END as u16, 0x99);
+    assert_eq!(memory_bus.read_byte(OAM_END as u16), 0x99);
+
+    memory_bus.write_byte(INTERRUPT_ENABLE_REGISTER as u16, 0xff);
+    memory_bus.write_byte(LCD_CONTROL_REGISTER as u16, 0x91);
+    for _ in 0..144 * 456 / 4 {
+        memory_bus.step(4);
+    }
+    assert_eq!(memory_bus.read_byte(LCD_Y_REGISTER as u16), 144);
+    assert_eq!(memory_bus.pending_interrupt(), Some(Interrupt::VBlank));
+    memory_bus.acknowledge_interrupt(Interrupt::VBlank);
+
+    memory_bus.write_byte(LCD_STATUS_REGISTER as u16, 0x10);
+    assert_eq!(memory_bus.pending_interrupt(), Some(Interrupt::LcdStat));
+    assert!(memory_bus.poll_frame().is_some());
+    assert_eq!(memory_bus.take_fault(), None);
+}
+
+#[test]
+fn test_oam_dma_copies_page_and_blocks_bus() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(Some(vec![0; BOOT_ROM_SIZE]), game_rom).unwrap();
+    for offset in 0..OAM_SIZE as u16 {
+        memory_bus.write_byte(0xC100 + offset, offset as u8 ^ 0x5A);
+    }
+    memory_bus.write_byte(ZERO_PAGE_BEGIN as u16, 0x77);
+    memory_bus.write_byte(OAM_DMA_REGISTER as u16, 0xC1);
+    memory_bus.step(4);
+    assert_eq!(memory_bus.read_byte(0xC100), 0x5A);
+    memory_bus.step(4);
+
+    // Only HRAM, IO and IE are reachable while the transfer runs
+    assert_eq!(memory_bus.read_byte(0xC100), OPEN_BUS_VALUE);
+    memory_bus.write_byte(0xC100, 0x00);
+    assert_eq!(memory_bus.read_byte(ZERO_PAGE_BEGIN as u16), 0x77);
+    assert_eq!(memory_bus.read_byte(OAM_DMA_REGISTER as u16), 0xC1);
+
+    memory_bus.step(252);
+    memory_bus.step(252);
+    // The M-cycle copying the last byte is still locked
+    memory_bus.step(132);
+    assert_eq!(memory_bus.read_byte(OAM_BEGIN as u16), OPEN_BUS_VALUE);
+    memory_bus.step(4);
+    assert_eq!(memory_bus.read_byte(0xC100), 0x5A);
+    for offset in 0..OAM_SIZE as u16 {
+        assert_eq!(memory_bus.read_byte(OAM_BEGIN as u16 + offset), offset as u8 ^ 0x5A);
+    }
+    assert_eq!(memory_bus.take_fault(), None);
+}
+
+#[test]
+fn test_joypad_press_requests_interrupt() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(Some(vec![0; BOOT_ROM_SIZE]), game_rom).unwrap();
+    memory_bus.write_byte(INTERRUPT_ENABLE_REGISTER as u16, 0xff);
+    memory_bus.write_byte(JOYPAD_REGISTER as u16, 0x10);
+    memory_bus.press_button(Button::Down);
+    assert_eq!(memory_bus.pending_interrupt(), None);
+    memory_bus.press_button(Button::Start);
+    assert_eq!(memory_bus.pending_interrupt(), Some(Interrupt::Joypad));
+    assert_eq!(memory_bus.read_byte(JOYPAD_REGISTER as u16), 0xD7);
+    memory_bus.release_button(Button::Start);
+    assert_eq!(memory_bus.read_byte(JOYPAD_REGISTER as u16), 0xDF);
+}
+
+#[test]
+fn test_serial_transfer_requests_interrupt() {
+    let game_rom: Vec<u8> = [0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE].to_vec();
+    let mut memory_bus = MemoryBus::new(Some(vec![0; BOOT_ROM_SIZE]), game_rom).unwrap();
+    let sink = crate::serial::CaptureSink::new();
+    memory_bus.connect_serial(Box::new(sink.clone()));
+    memory_bus.write_byte(INTERRUPT_ENABLE_REGISTER as u16, 0xff);
+    memory_bus.write_byte(SERIAL_DATA_REGISTER as u16, b'o');
+    memory_bus.write_byte(SERIAL_CONTROL_REGISTER as u16, 0x81);
+    for _ in 0..4096 / 4 {
+        memory_bus.step(4);
+    }
+    assert_eq!(memory_bus.pending_interrupt(), Some(Interrupt::Serial));
+    assert_eq!(sink.take(), b"o");
+}
+
+#[test]
+fn test_rtc_follows_the_given_clock() {
+    let mut game_rom: Vec<u8> = vec![0; ROM_BANK_0_SIZE + ROM_BANK_N_SIZE];
+    game_rom[0x0147] = 0x10;
+    game_rom[0x0149] = 0x02;
+    let clock = crate::cartridge::rtc::tests::ManualClock::default();
+    let mut memory_bus =
+        MemoryBus::with_config(Config::default(), None, game_rom, Box::new(clock.clone())).unwrap();
+    memory_bus.write_byte(0x0000, 0x0A);
+    memory_bus.write_byte(0x4000, 0x08);
+    clock.advance(42);
+    memory_bus.write_byte(0x6000, 0x00);
+    memory_bus.write_byte(0x6000, 0x01);
+    assert_eq!(memory_bus.read_byte(CARTRIDGE_RAM_BEGIN as u16), 42);
+}
@@ -0,0 +1,73 @@
+use crate::memory_bus::{BOOT_ROM_SIZE, CGB_BOOT_ROM_SIZE};
+
+// The console being emulated. Games tell them apart by the registers the boot ROM leaves behind
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Model {
+    // Early original Game Boy with a different boot ROM
+    DMG0,
+    #[default]
+    DMG,
+    // Game Boy Pocket and Light
+    MGB,
+    SGB,
+    SGB2,
+    CGB,
+    // Game Boy Advance running in CGB mode
+    AGB,
+}
+
+impl Model {
+    // Whether CGB only hardware such as KEY1 is present
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::CGB | Model::AGB)
+    }
+
+    pub fn boot_rom_size(self) -> usize {
+        if self.is_cgb() {
+            CGB_BOOT_ROM_SIZE
+        } else {
+            BOOT_ROM_SIZE
+        }
+    }
+
+    // Internal divider counter when the boot ROM hands over. Only DMG0, DMG and MGB are well
+    // documented, the others depend on how long the logo animation ran so start from zero
+    pub fn post_boot_divider(self) -> u16 {
+        match self {
+            Model::DMG0 => 0x1800,
+            Model::DMG | Model::MGB => 0xABCC,
+            _ => 0x0000,
+        }
+    }
+
+    // The VBlank line the PPU is on when the boot ROM hands over. DMG0 finishes early, the rest
+    // are on the last line after LY has already wrapped to 0
+    pub fn post_boot_line(self) -> u8 {
+        match self {
+            Model::DMG0 => 0x91,
+            _ => 153,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_rom_size() {
+        assert_eq!(Model::DMG.boot_rom_size(), 256);
+        assert_eq!(Model::SGB2.boot_rom_size(), 256);
+        assert_eq!(Model::CGB.boot_rom_size(), 2304);
+        assert_eq!(Model::AGB.boot_rom_size(), 2304);
+    }
+
+    #[test]
+    fn test_is_cgb() {
+        assert!(!Model::DMG0.is_cgb());
+        assert!(!Model::MGB.is_cgb());
+        assert!(!Model::SGB.is_cgb());
+        assert!(Model::CGB.is_cgb());
+        assert!(Model::AGB.is_cgb());
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::VecDeque;
+
+use super::framebuffer::SCREEN_WIDTH;
+use super::{
+    apply_palette, Ppu, Sprite, BACKGROUND_ENABLE, BACKGROUND_TILE_MAP, SPRITE_BEHIND_BACKGROUND,
+    SPRITE_ENABLE, SPRITE_PALETTE_1, WINDOW_ENABLE, WINDOW_TILE_MAP, WINDOW_X_OFFSET,
+};
+
+// Every fetcher step except pushing takes two dots
+const FETCHER_STEP_DOTS: u8 = 2;
+// Time a sprite's tile row takes to read once the background fetcher has let go of VRAM
+const SPRITE_FETCH_DOTS: u8 = 6;
+// The first tile of every line is fetched twice and the first result thrown away
+const DISCARDED_FETCH_DOTS: u8 = 6;
+const TILE_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    // Waits until the background FIFO is empty
+    Push,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SpritePixel {
+    color: u8,
+    palette: usize,
+    behind_background: bool,
+}
+
+// State of mode 3 for the dot by dot backend. Registers are read at the moment the hardware
+// would read them, so changes in the middle of a line show up from that point on
+pub struct PixelFifo {
+    background: VecDeque<u8>,
+    sprites: VecDeque<SpritePixel>,
+    step: FetcherStep,
+    step_dots: u8,
+    // Tile column the fetcher is on, counted from the left edge or the window's edge
+    fetcher_x: u8,
+    tile_row: usize,
+    data_low: u8,
+    data_high: u8,
+    // Pixels dropped at the start of the line to apply SCX's fine scroll
+    discard: u8,
+    // Next screen column to be written
+    x: usize,
+    fetching_window: bool,
+    // Sprites on this line in OAM order, removed once fetched
+    line_sprites: Vec<Sprite>,
+    // Dots left before the fetcher and output resume
+    stall_dots: u8,
+    // Merged into the sprite FIFO when the stall ends
+    sprite_being_fetched: Option<Sprite>,
+}
+
+impl PixelFifo {
+    pub fn new() -> PixelFifo {
+        PixelFifo {
+            background: VecDeque::with_capacity(TILE_WIDTH * 2),
+            sprites: VecDeque::with_capacity(TILE_WIDTH),
+            step: FetcherStep::Tile,
+            step_dots: 0,
+            fetcher_x: 0,
+            tile_row: 0,
+            data_low: 0,
+            data_high: 0,
+            discard: 0,
+            x: 0,
+            fetching_window: false,
+            line_sprites: Vec::new(),
+            stall_dots: 0,
+            sprite_being_fetched: None,
+        }
+    }
+}
+
+impl Ppu {
+    pub(super) fn start_fifo_line(&mut self) {
+        let line_sprites = self.scan_oam();
+        let fifo = &mut self.fifo;
+        fifo.background.clear();
+        fifo.sprites.clear();
+        fifo.step = FetcherStep::Tile;
+        fifo.step_dots = 0;
+        fifo.fetcher_x = 0;
+        fifo.discard = self.scroll_x % 8;
+        fifo.x = 0;
+        fifo.fetching_window = false;
+        fifo.line_sprites = line_sprites;
+        fifo.stall_dots = DISCARDED_FETCH_DOTS;
+        fifo.sprite_being_fetched = None;
+    }
+
+    // Runs one dot of mode 3, returns true once the last pixel of the line has been written
+    pub(super) fn tick_fifo(&mut self) -> bool {
+        if self.fifo.stall_dots > 0 {
+            // A sprite fetch waits for the background fetcher to finish the tile it is on
+            if self.fifo.sprite_being_fetched.is_some() {
+                self.tick_fetcher();
+            }
+            self.fifo.stall_dots -= 1;
+            if self.fifo.stall_dots == 0 {
+                self.merge_sprite();
+            }
+            return false;
+        }
+
+        self.tick_fetcher();
+
+        // A sprite starting at this column stops pixel output until it has been fetched. Sprites
+        // clipped by the left edge all come due together, the lowest X goes first so it wins the
+        // merge, ties go to the earlier OAM entry
+        if self.lcd_control & SPRITE_ENABLE != 0 && !self.fifo.background.is_empty() {
+            let x = self.fifo.x as i16;
+            let next = self
+                .fifo
+                .line_sprites
+                .iter()
+                .enumerate()
+                .filter(|(_, sprite)| sprite.x <= x)
+                .min_by_key(|(_, sprite)| sprite.x)
+                .map(|(index, _)| index);
+            if let Some(index) = next {
+                let sprite = self.fifo.line_sprites.remove(index);
+                self.fifo.sprite_being_fetched = Some(sprite);
+                // The last dot of the background fetch overlaps the first of the sprite's, so
+                // the stall is anywhere from 6 to 11 dots counting this one
+                let wait = self.fetcher_dots_left();
+                self.fifo.stall_dots = SPRITE_FETCH_DOTS - 1 + wait.saturating_sub(1);
+                return false;
+            }
+        }
+
+        if self.window_starts_here() {
+            let fifo = &mut self.fifo;
+            fifo.fetching_window = true;
+            fifo.background.clear();
+            fifo.step = FetcherStep::Tile;
+            fifo.step_dots = 0;
+            fifo.fetcher_x = 0;
+            return false;
+        }
+
+        self.push_pixel()
+    }
+
+    fn window_starts_here(&self) -> bool {
+        !self.fifo.fetching_window
+            && self.lcd_control & BACKGROUND_ENABLE != 0
+            && self.lcd_control & WINDOW_ENABLE != 0
+            && self.window_y_matched
+            && self.fifo.discard == 0
+            && self.fifo.x as u16 + WINDOW_X_OFFSET as u16 >= self.window_x as u16
+    }
+
+    // Dots until the background fetcher has its tile data and only waits to push it
+    fn fetcher_dots_left(&self) -> u8 {
+        let steps_left = match self.fifo.step {
+            FetcherStep::Tile => 3,
+            FetcherStep::DataLow => 2,
+            FetcherStep::DataHigh => 1,
+            FetcherStep::Push => return 0,
+        };
+        steps_left * FETCHER_STEP_DOTS - self.fifo.step_dots
+    }
+
+    fn tick_fetcher(&mut self) {
+        if self.fifo.step == FetcherStep::Push {
+            if self.fifo.background.is_empty() {
+                for bit in (0..TILE_WIDTH).rev() {
+                    let low = (self.fifo.data_low >> bit) & 1;
+                    let high = (self.fifo.data_high >> bit) & 1;
+                    self.fifo.background.push_back(high << 1 | low);
+                }
+                self.fifo.fetcher_x = self.fifo.fetcher_x.wrapping_add(1);
+                self.fifo.step = FetcherStep::Tile;
+                self.fifo.step_dots = 0;
+            }
+            return;
+        }
+
+        self.fifo.step_dots += 1;
+        if self.fifo.step_dots < FETCHER_STEP_DOTS {
+            return;
+        }
+        self.fifo.step_dots = 0;
+        match self.fifo.step {
+            FetcherStep::Tile => {
+                let (map, map_x, map_y) = if self.fifo.fetching_window {
+                    (self.tile_map(WINDOW_TILE_MAP), self.fifo.fetcher_x, self.window_line)
+                } else {
+                    let map_x = (self.scroll_x / 8).wrapping_add(self.fifo.fetcher_x) & 31;
+                    let map_y = self.scroll_y.wrapping_add(self.line);
+                    (self.tile_map(BACKGROUND_TILE_MAP), map_x, map_y)
+                };
+                let tile_index = self.vram[map + (map_y as usize / 8) * 32 + map_x as usize % 32];
+                self.fifo.tile_row = self.tile_address(tile_index) + (map_y as usize % 8) * 2;
+                self.fifo.step = FetcherStep::DataLow;
+            }
+            FetcherStep::DataLow => {
+                self.fifo.data_low = self.vram[self.fifo.tile_row];
+                self.fifo.step = FetcherStep::DataHigh;
+            }
+            FetcherStep::DataHigh => {
+                self.fifo.data_high = self.vram[self.fifo.tile_row + 1];
+                self.fifo.step = FetcherStep::Push;
+            }
+            FetcherStep::Push => unreachable!(),
+        }
+    }
+
+    // Sprite pixels only land on transparent slots, so whichever sprite got there first wins
+    fn merge_sprite(&mut self) {
+        let sprite = match self.fifo.sprite_being_fetched.take() {
+            Some(sprite) => sprite,
+            None => return,
+        };
+        let line = self.line as i16;
+        let height = self.sprite_height();
+        let palette = if sprite.attributes & SPRITE_PALETTE_1 != 0 { 1 } else { 0 };
+        let behind_background = sprite.attributes & SPRITE_BEHIND_BACKGROUND != 0;
+        for column in 0..TILE_WIDTH as i16 {
+            // Columns left of the screen edge have already gone by
+            let offset = sprite.x + column - self.fifo.x as i16;
+            if offset < 0 {
+                continue;
+            }
+            let offset = offset as usize;
+            while self.fifo.sprites.len() <= offset {
+                self.fifo.sprites.push_back(SpritePixel::default());
+            }
+            let color = self.sprite_pixel(&sprite, column, line - sprite.y, height);
+            if self.fifo.sprites[offset].color == 0 {
+                self.fifo.sprites[offset] = SpritePixel { color, palette, behind_background };
+            }
+        }
+    }
+
+    fn push_pixel(&mut self) -> bool {
+        let background = match self.fifo.background.pop_front() {
+            Some(color) => color,
+            None => return false,
+        };
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return false;
+        }
+        let sprite = self.fifo.sprites.pop_front().unwrap_or_default();
+
+        let background_enabled = self.lcd_control & BACKGROUND_ENABLE != 0;
+        let background = if background_enabled { background } else { 0 };
+        let x = self.fifo.x;
+        self.background_line[x] = background;
+
+        let sprite_visible = sprite.color != 0
+            && self.lcd_control & SPRITE_ENABLE != 0
+            && !(sprite.behind_background && background != 0);
+        let shade = if sprite_visible {
+            apply_palette(self.object_palettes[sprite.palette], sprite.color)
+        } else if background_enabled {
+            apply_palette(self.background_palette, background)
+        } else {
+            0
+        };
+        self.framebuffer.set_shade(x, self.line as usize, shade);
+
+        self.fifo.x += 1;
+        if self.fifo.x < SCREEN_WIDTH {
+            return false;
+        }
+        if self.fifo.fetching_window {
+            self.window_line += 1;
+        }
+        true
+    }
+}
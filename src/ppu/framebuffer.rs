@@ -0,0 +1,65 @@
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+// Light to dark greys for shades 0-3
+pub const GREYSCALE: [[u8; 3]; 4] = [
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0x00, 0x00, 0x00],
+];
+
+// One finished frame as shade indices 0-3 after the palettes have been applied
+#[derive(Clone)]
+pub struct Framebuffer {
+    shades: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            shades: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn shade(&self, x: usize, y: usize) -> u8 {
+        self.shades[y * SCREEN_WIDTH + x]
+    }
+
+    pub fn set_shade(&mut self, x: usize, y: usize, shade: u8) {
+        self.shades[y * SCREEN_WIDTH + x] = shade & 0b11;
+    }
+
+    // Row major, one byte per pixel
+    pub fn shades(&self) -> &[u8] {
+        &self.shades
+    }
+
+    // Row major RGB triples using the given colour for each shade
+    pub fn to_rgb(&self, palette: &[[u8; 3]; 4]) -> Vec<u8> {
+        self.shades
+            .iter()
+            .flat_map(|&shade| palette[shade as usize].iter().copied())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rgb() {
+        let mut framebuffer = Framebuffer::new();
+        framebuffer.set_shade(1, 0, 3);
+        let rgb = framebuffer.to_rgb(&GREYSCALE);
+        assert_eq!(rgb.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        assert_eq!(&rgb[0..6], &[0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00]);
+    }
+}
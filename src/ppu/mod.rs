@@ -0,0 +1,992 @@
+mod fifo;
+pub mod framebuffer;
+
+use crate::memory_bus::{
+    BACKGROUND_PALETTE_REGISTER, LCD_CONTROL_REGISTER, LCD_STATUS_REGISTER, LCD_Y_COMPARE_REGISTER,
+    LCD_Y_REGISTER, OAM_BEGIN, OAM_SIZE, OBJECT_PALETTE_0_REGISTER, OBJECT_PALETTE_1_REGISTER,
+    SCROLL_X_REGISTER, SCROLL_Y_REGISTER, VRAM_BEGIN, VRAM_SIZE, WINDOW_X_REGISTER,
+    WINDOW_Y_REGISTER,
+};
+
+use self::fifo::PixelFifo;
+use self::framebuffer::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub(crate) const DOTS_PER_LINE: u16 = 456;
+pub(crate) const LINES_PER_FRAME: u8 = 154;
+// LY already reads 0 this far into the last line of VBlank
+const LY_WRAP_DOTS: u16 = 4;
+pub(crate) const OAM_SCAN_DOTS: u16 = 80;
+// Mode 3 with no scrolling, window or sprites. Those only ever make it longer
+const MINIMUM_DRAWING_DOTS: u16 = 172;
+const WINDOW_DRAWING_PENALTY: u16 = 6;
+
+// STAT interrupt source selects
+const LY_COMPARE_INTERRUPT: u8 = 1 << 6;
+const OAM_SCAN_INTERRUPT: u8 = 1 << 5;
+const VBLANK_INTERRUPT: u8 = 1 << 4;
+const HBLANK_INTERRUPT: u8 = 1 << 3;
+const LY_COMPARE_FLAG: u8 = 1 << 2;
+const LCD_STATUS_WRITABLE_BITS: u8 = 0b0111_1000;
+const LCD_STATUS_UNUSED_BITS: u8 = 0b1000_0000;
+
+const LCD_ENABLE: u8 = 1 << 7;
+const WINDOW_TILE_MAP: u8 = 1 << 6;
+const WINDOW_ENABLE: u8 = 1 << 5;
+const TILE_DATA_UNSIGNED: u8 = 1 << 4;
+const BACKGROUND_TILE_MAP: u8 = 1 << 3;
+const TALL_SPRITES: u8 = 1 << 2;
+const SPRITE_ENABLE: u8 = 1 << 1;
+// On the DMG this blanks the window too
+const BACKGROUND_ENABLE: u8 = 1 << 0;
+
+// Offsets into VRAM
+const TILE_MAP_0: usize = 0x1800;
+const TILE_MAP_1: usize = 0x1C00;
+// Base for signed tile indices when LCDC bit 4 is clear
+const SIGNED_TILE_DATA: usize = 0x1000;
+
+// WX is the window's left edge plus 7
+const WINDOW_X_OFFSET: u8 = 7;
+
+const SPRITE_COUNT: usize = 40;
+const SPRITES_PER_LINE: usize = 10;
+// OAM positions are offset so sprites can be partly off the top and left edges
+const SPRITE_Y_OFFSET: i16 = 16;
+const SPRITE_X_OFFSET: i16 = 8;
+
+const SPRITE_BEHIND_BACKGROUND: u8 = 1 << 7;
+const SPRITE_Y_FLIP: u8 = 1 << 6;
+const SPRITE_X_FLIP: u8 = 1 << 5;
+const SPRITE_PALETTE_1: u8 = 1 << 4;
+
+// How mode 3 turns VRAM into pixels. Both fill the same framebuffer
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PpuBackend {
+    // Draws each line in one go at the end of mode 3, with mode 3's length estimated. Fast, but
+    // register writes in the middle of a line only affect the next one
+    #[default]
+    Scanline,
+    // Runs the background fetcher and the pixel FIFOs every dot, so mid-line writes take effect
+    // at the right pixel and mode 3's length falls out of the emulation
+    PixelFifo,
+}
+
+// The value of STAT bits 0-1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+// Interrupts the PPU raised during a step
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PpuInterrupts {
+    pub vblank: bool,
+    pub stat: bool,
+}
+
+// One OAM entry with its position converted to screen coordinates
+#[derive(Debug, Clone, Copy)]
+struct Sprite {
+    y: i16,
+    x: i16,
+    tile: u8,
+    attributes: u8,
+}
+
+pub struct Ppu {
+    backend: PpuBackend,
+    vram: [u8; VRAM_SIZE],
+    oam: [u8; OAM_SIZE],
+    lcd_control: u8,
+    lcd_status: u8,
+    scroll_y: u8,
+    scroll_x: u8,
+    line: u8,
+    line_compare: u8,
+    background_palette: u8,
+    object_palettes: [u8; 2],
+    window_y: u8,
+    window_x: u8,
+    mode: Mode,
+    // Dots spent on the current line
+    dots: u16,
+    // How long mode 3 lasts on the current line
+    drawing_dots: u16,
+    // The OR of every enabled STAT source. The interrupt fires only when it goes from low to high
+    stat_line: bool,
+    // The window keeps its own line counter that only advances on lines it was drawn on
+    window_line: u8,
+    // Whether WY has matched LY as a line started drawing this frame. Only then can the window
+    // show, and later WY writes don't take it away until the next frame
+    window_y_matched: bool,
+    // The first line after the LCD is switched on has no OAM scan, it reads as mode 0 until
+    // drawing starts at the usual dot
+    first_line: bool,
+    // Colour indices before the palette, sprites need them to resolve priority
+    background_line: [u8; SCREEN_WIDTH],
+    fifo: PixelFifo,
+    framebuffer: Framebuffer,
+    frame_ready: bool,
+}
+
+impl Default for Ppu {
+    fn default() -> Ppu {
+        Ppu::new(PpuBackend::default())
+    }
+}
+
+impl Ppu {
+    pub fn new(backend: PpuBackend) -> Ppu {
+        Ppu {
+            backend,
+            vram: [0; VRAM_SIZE],
+            oam: [0; OAM_SIZE],
+            lcd_control: 0,
+            lcd_status: 0,
+            scroll_y: 0,
+            scroll_x: 0,
+            line: 0,
+            line_compare: 0,
+            background_palette: 0,
+            object_palettes: [0; 2],
+            window_y: 0,
+            window_x: 0,
+            mode: Mode::HBlank,
+            dots: 0,
+            drawing_dots: MINIMUM_DRAWING_DOTS,
+            stat_line: false,
+            window_line: 0,
+            window_y_matched: false,
+            first_line: false,
+            background_line: [0; SCREEN_WIDTH],
+            fifo: PixelFifo::new(),
+            framebuffer: Framebuffer::new(),
+            frame_ready: false,
+        }
+    }
+
+    pub fn read_vram(&self, address: u16) -> u8 {
+        self.vram[address as usize - VRAM_BEGIN]
+    }
+
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        self.vram[address as usize - VRAM_BEGIN] = value;
+    }
+
+    pub fn read_oam(&self, address: u16) -> u8 {
+        self.oam[address as usize - OAM_BEGIN]
+    }
+
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        self.oam[address as usize - OAM_BEGIN] = value;
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address as usize {
+            LCD_CONTROL_REGISTER => self.lcd_control,
+            LCD_STATUS_REGISTER => {
+                let coincidence = if self.ly() == self.line_compare { LY_COMPARE_FLAG } else { 0 };
+                LCD_STATUS_UNUSED_BITS | self.lcd_status | coincidence | self.mode as u8
+            }
+            SCROLL_Y_REGISTER => self.scroll_y,
+            SCROLL_X_REGISTER => self.scroll_x,
+            LCD_Y_REGISTER => self.ly(),
+            LCD_Y_COMPARE_REGISTER => self.line_compare,
+            BACKGROUND_PALETTE_REGISTER => self.background_palette,
+            OBJECT_PALETTE_0_REGISTER => self.object_palettes[0],
+            OBJECT_PALETTE_1_REGISTER => self.object_palettes[1],
+            WINDOW_Y_REGISTER => self.window_y,
+            WINDOW_X_REGISTER => self.window_x,
+            // The bus never routes other addresses here, treat them as open bus
+            _ => 0xFF,
+        }
+    }
+
+    // Returns whether the write raised a STAT interrupt, which changing the sources or LYC can do
+    pub fn write(&mut self, address: u16, value: u8) -> bool {
+        match address as usize {
+            LCD_CONTROL_REGISTER => {
+                let was_enabled = self.lcd_enabled();
+                self.lcd_control = value;
+                // Switching the LCD off parks it at the start of the frame in HBlank
+                if was_enabled && !self.lcd_enabled() {
+                    self.line = 0;
+                    self.dots = 0;
+                    self.window_line = 0;
+                    self.window_y_matched = false;
+                    self.mode = Mode::HBlank;
+                    self.stat_line = false;
+                } else if !was_enabled && self.lcd_enabled() {
+                    self.first_line = true;
+                    return self.update_stat_line();
+                }
+            }
+            LCD_STATUS_REGISTER => {
+                self.lcd_status = value & LCD_STATUS_WRITABLE_BITS;
+                return self.update_stat_line();
+            }
+            SCROLL_Y_REGISTER => self.scroll_y = value,
+            SCROLL_X_REGISTER => self.scroll_x = value,
+            // LY is read only
+            LCD_Y_REGISTER => {}
+            LCD_Y_COMPARE_REGISTER => {
+                self.line_compare = value;
+                return self.update_stat_line();
+            }
+            BACKGROUND_PALETTE_REGISTER => self.background_palette = value,
+            OBJECT_PALETTE_0_REGISTER => self.object_palettes[0] = value,
+            OBJECT_PALETTE_1_REGISTER => self.object_palettes[1] = value,
+            WINDOW_Y_REGISTER => self.window_y = value,
+            WINDOW_X_REGISTER => self.window_x = value,
+            _ => {}
+        }
+        false
+    }
+
+    // Advances by the given number of dots
+    pub fn step(&mut self, dots: u8) -> PpuInterrupts {
+        let mut interrupts = PpuInterrupts::default();
+        if !self.lcd_enabled() {
+            return interrupts;
+        }
+        for _ in 0..dots {
+            self.tick(&mut interrupts);
+        }
+        interrupts
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // The last completed frame, once per frame
+    pub fn take_frame(&mut self) -> Option<&Framebuffer> {
+        if !self.frame_ready {
+            return None;
+        }
+        self.frame_ready = false;
+        Some(&self.framebuffer)
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    // Puts the PPU in VBlank on the line the boot ROM hands over on, just after LY has wrapped
+    // if that is the last one
+    pub fn skip_boot(&mut self, line: u8) {
+        self.line = line;
+        self.dots = LY_WRAP_DOTS;
+        self.mode = Mode::VBlank;
+        self.first_line = false;
+        self.window_y_matched = false;
+        self.update_stat_line();
+    }
+
+    fn lcd_enabled(&self) -> bool {
+        self.lcd_control & LCD_ENABLE != 0
+    }
+
+    fn tick(&mut self, interrupts: &mut PpuInterrupts) {
+        self.dots += 1;
+        match self.mode {
+            Mode::OamScan if self.dots == OAM_SCAN_DOTS => self.start_drawing(),
+            Mode::HBlank if self.first_line && self.dots == OAM_SCAN_DOTS => {
+                self.first_line = false;
+                self.start_drawing();
+            }
+            Mode::Drawing => {
+                let line_done = match self.backend {
+                    PpuBackend::Scanline => {
+                        let done = self.dots == OAM_SCAN_DOTS + self.drawing_dots;
+                        if done {
+                            self.render_scanline();
+                        }
+                        done
+                    }
+                    PpuBackend::PixelFifo => self.tick_fifo(),
+                };
+                if line_done {
+                    self.mode = Mode::HBlank;
+                }
+            }
+            _ => {}
+        }
+
+        if self.dots == DOTS_PER_LINE {
+            self.dots = 0;
+            self.line += 1;
+            if self.line == LINES_PER_FRAME {
+                self.line = 0;
+                self.window_line = 0;
+            }
+            if self.line as usize == SCREEN_HEIGHT {
+                self.mode = Mode::VBlank;
+                self.window_y_matched = false;
+                self.frame_ready = true;
+                interrupts.vblank = true;
+            } else if (self.line as usize) < SCREEN_HEIGHT {
+                self.mode = Mode::OamScan;
+            }
+        }
+
+        interrupts.stat |= self.update_stat_line();
+    }
+
+    // What LY reads, which is 0 for most of line 153
+    fn ly(&self) -> u8 {
+        if self.line == LINES_PER_FRAME - 1 && self.dots >= LY_WRAP_DOTS {
+            0
+        } else {
+            self.line
+        }
+    }
+
+    fn start_drawing(&mut self) {
+        if self.window_y == self.line {
+            self.window_y_matched = true;
+        }
+        match self.backend {
+            PpuBackend::Scanline => self.drawing_dots = self.drawing_length(),
+            PpuBackend::PixelFifo => self.start_fifo_line(),
+        }
+        self.mode = Mode::Drawing;
+    }
+
+    // Re-evaluates the STAT sources, returns true on a rising edge. While one source holds the
+    // line high, others becoming true don't raise another interrupt
+    fn update_stat_line(&mut self) -> bool {
+        if !self.lcd_enabled() {
+            return false;
+        }
+        let mode_source = match self.mode {
+            Mode::HBlank => HBLANK_INTERRUPT,
+            Mode::VBlank => VBLANK_INTERRUPT,
+            Mode::OamScan => OAM_SCAN_INTERRUPT,
+            Mode::Drawing => 0,
+        };
+        let line = self.lcd_status & mode_source != 0
+            || (self.lcd_status & LY_COMPARE_INTERRUPT != 0 && self.ly() == self.line_compare);
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        rising
+    }
+
+    // Mode 3 gets longer for fine scrolling, the window and each sprite on the line. The sprite
+    // cost is Pan Docs' 6 to 11 dot estimate depending on where it sits against the background
+    fn drawing_length(&self) -> u16 {
+        let mut length = MINIMUM_DRAWING_DOTS + (self.scroll_x % 8) as u16;
+        if self.window_visible_on_line() {
+            length += WINDOW_DRAWING_PENALTY;
+        }
+        if self.lcd_control & SPRITE_ENABLE != 0 {
+            for sprite in self.scan_oam() {
+                let alignment = (sprite.x + SPRITE_X_OFFSET + self.scroll_x as i16).rem_euclid(8);
+                length += 11 - alignment.min(5) as u16;
+            }
+        }
+        length
+    }
+
+    fn window_visible_on_line(&self) -> bool {
+        self.lcd_control & BACKGROUND_ENABLE != 0
+            && self.lcd_control & WINDOW_ENABLE != 0
+            && self.window_y_matched
+            && self.window_x < SCREEN_WIDTH as u8 + WINDOW_X_OFFSET
+    }
+
+    fn render_scanline(&mut self) {
+        let y = self.line;
+        let background_enabled = self.lcd_control & BACKGROUND_ENABLE != 0;
+        let window_visible = self.window_visible_on_line();
+
+        for x in 0..SCREEN_WIDTH {
+            let screen_x = x as u8 + WINDOW_X_OFFSET;
+            let color = if window_visible && screen_x >= self.window_x {
+                let map = self.tile_map(WINDOW_TILE_MAP);
+                self.tile_map_pixel(map, screen_x - self.window_x, self.window_line)
+            } else if background_enabled {
+                let map = self.tile_map(BACKGROUND_TILE_MAP);
+                let map_x = self.scroll_x.wrapping_add(x as u8);
+                let map_y = self.scroll_y.wrapping_add(y);
+                self.tile_map_pixel(map, map_x, map_y)
+            } else {
+                0
+            };
+            self.background_line[x] = color;
+            let shade = if background_enabled {
+                apply_palette(self.background_palette, color)
+            } else {
+                0
+            };
+            self.framebuffer.set_shade(x, y as usize, shade);
+        }
+
+        if window_visible {
+            self.window_line += 1;
+        }
+
+        if self.lcd_control & SPRITE_ENABLE != 0 {
+            self.render_sprites();
+        }
+    }
+
+    fn sprite_height(&self) -> i16 {
+        if self.lcd_control & TALL_SPRITES != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    // The first 10 sprites in OAM order that overlap the current line, X doesn't matter
+    fn scan_oam(&self) -> Vec<Sprite> {
+        let line = self.line as i16;
+        let height = self.sprite_height();
+        self.oam
+            .chunks(4)
+            .take(SPRITE_COUNT)
+            .map(|entry| Sprite {
+                y: entry[0] as i16 - SPRITE_Y_OFFSET,
+                x: entry[1] as i16 - SPRITE_X_OFFSET,
+                tile: entry[2],
+                attributes: entry[3],
+            })
+            .filter(|sprite| line >= sprite.y && line < sprite.y + height)
+            .take(SPRITES_PER_LINE)
+            .collect()
+    }
+
+    fn render_sprites(&mut self) {
+        let mut sprites = self.scan_oam();
+        // On the DMG the lowest X wins, ties go to the earlier OAM entry. The sort is stable
+        sprites.sort_by_key(|sprite| sprite.x);
+        let line = self.line as i16;
+        let height = self.sprite_height();
+
+        for x in 0..SCREEN_WIDTH {
+            let screen_x = x as i16;
+            let winner = sprites.iter().find_map(|sprite| {
+                if screen_x < sprite.x || screen_x >= sprite.x + 8 {
+                    return None;
+                }
+                let color = self.sprite_pixel(sprite, screen_x - sprite.x, line - sprite.y, height);
+                // Transparent pixels let the next sprite show through
+                if color == 0 {
+                    None
+                } else {
+                    Some((sprite, color))
+                }
+            });
+
+            if let Some((sprite, color)) = winner {
+                let hidden = sprite.attributes & SPRITE_BEHIND_BACKGROUND != 0
+                    && self.background_line[x] != 0;
+                if !hidden {
+                    let palette = if sprite.attributes & SPRITE_PALETTE_1 != 0 {
+                        self.object_palettes[1]
+                    } else {
+                        self.object_palettes[0]
+                    };
+                    self.framebuffer.set_shade(x, line as usize, apply_palette(palette, color));
+                }
+            }
+        }
+    }
+
+    // Sprites always use the unsigned tile data at 0x8000
+    fn sprite_pixel(&self, sprite: &Sprite, x: i16, y: i16, height: i16) -> u8 {
+        let x = if sprite.attributes & SPRITE_X_FLIP != 0 { 7 - x } else { x };
+        let y = if sprite.attributes & SPRITE_Y_FLIP != 0 { height - 1 - y } else { y };
+        // Tall sprites ignore bit 0 of the tile index and run into the next tile
+        let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+        let tile = tile as usize * 16 + (y as usize / 8) * 16;
+        self.tile_pixel(tile, x as u8, y as u8 % 8)
+    }
+
+    fn tile_map(&self, select_bit: u8) -> usize {
+        if self.lcd_control & select_bit != 0 {
+            TILE_MAP_1
+        } else {
+            TILE_MAP_0
+        }
+    }
+
+    // Colour index of a pixel in the 256x256 picture a tile map describes
+    fn tile_map_pixel(&self, map: usize, x: u8, y: u8) -> u8 {
+        let tile_index = self.vram[map + (y as usize / 8) * 32 + x as usize / 8];
+        self.tile_pixel(self.tile_address(tile_index), x % 8, y % 8)
+    }
+
+    // VRAM offset of a background or window tile under the current addressing mode
+    fn tile_address(&self, tile_index: u8) -> usize {
+        if self.lcd_control & TILE_DATA_UNSIGNED != 0 {
+            tile_index as usize * 16
+        } else {
+            (SIGNED_TILE_DATA as isize + tile_index as i8 as isize * 16) as usize
+        }
+    }
+
+    // Each tile row is two bytes, the first holds the low bit of every pixel's colour
+    fn tile_pixel(&self, tile: usize, x: u8, y: u8) -> u8 {
+        let row = tile + y as usize * 2;
+        let bit = 7 - x;
+        let low = (self.vram[row] >> bit) & 1;
+        let high = (self.vram[row + 1] >> bit) & 1;
+        high << 1 | low
+    }
+}
+
+// Palettes hold two bits per colour index, index 0 in the lowest bits
+fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY_PALETTE: u8 = 0b11_10_01_00;
+
+    fn ppu_with_control(lcd_control: u8) -> Ppu {
+        ppu_with_backend(PpuBackend::Scanline, lcd_control)
+    }
+
+    fn ppu_with_backend(backend: PpuBackend, lcd_control: u8) -> Ppu {
+        let mut ppu = Ppu::new(backend);
+        ppu.write(BACKGROUND_PALETTE_REGISTER as u16, IDENTITY_PALETTE);
+        ppu.write(LCD_CONTROL_REGISTER as u16, lcd_control);
+        ppu
+    }
+
+    // Fills a tile so every pixel has the given colour index
+    fn solid_tile(ppu: &mut Ppu, tile: usize, color: u8) {
+        let low = if color & 1 != 0 { 0xFF } else { 0x00 };
+        let high = if color & 2 != 0 { 0xFF } else { 0x00 };
+        for row in 0..8 {
+            ppu.vram[tile + row * 2] = low;
+            ppu.vram[tile + row * 2 + 1] = high;
+        }
+    }
+
+    fn run_lines(ppu: &mut Ppu, lines: u8) {
+        for _ in 0..lines {
+            ppu.step(228);
+            ppu.step(228);
+        }
+    }
+
+    fn run_frame(ppu: &mut Ppu) {
+        for _ in 0..(DOTS_PER_LINE as usize * LINES_PER_FRAME as usize / 4) {
+            ppu.step(4);
+        }
+    }
+
+    #[test]
+    fn test_vblank_after_144_lines() {
+        let mut ppu = ppu_with_control(0x91);
+        for _ in 0..143 {
+            assert!(!ppu.step(228).vblank);
+            assert!(!ppu.step(228).vblank);
+        }
+        assert!(ppu.take_frame().is_none());
+        assert!(!ppu.step(228).vblank);
+        assert!(ppu.step(228).vblank);
+        assert_eq!(ppu.mode(), Mode::VBlank);
+        assert_eq!(ppu.read(LCD_Y_REGISTER as u16), 144);
+        assert!(ppu.take_frame().is_some());
+        assert!(ppu.take_frame().is_none());
+    }
+
+    #[test]
+    fn test_lcd_off_holds_line_zero() {
+        let mut ppu = ppu_with_control(0x91);
+        ppu.step(228);
+        ppu.step(228);
+        assert_eq!(ppu.read(LCD_Y_REGISTER as u16), 1);
+        ppu.write(LCD_CONTROL_REGISTER as u16, 0x11);
+        assert!(!ppu.step(228).vblank);
+        assert_eq!(ppu.read(LCD_Y_REGISTER as u16), 0);
+        assert_eq!(ppu.read(LCD_STATUS_REGISTER as u16) & 0b11, 0);
+
+        // The first line skips OAM scan, it shows mode 0 until drawing starts at dot 80 and the
+        // mode 2 STAT source stays quiet
+        ppu.write(LCD_STATUS_REGISTER as u16, OAM_SCAN_INTERRUPT);
+        assert!(!ppu.write(LCD_CONTROL_REGISTER as u16, 0x91));
+        assert_eq!(ppu.read(LCD_STATUS_REGISTER as u16) & 0b11, 0);
+        assert!(!ppu.step(79).stat);
+        assert_eq!(ppu.mode(), Mode::HBlank);
+        ppu.step(1);
+        assert_eq!(ppu.mode(), Mode::Drawing);
+    }
+
+    fn dots_in_mode(ppu: &mut Ppu, mode: Mode) -> u16 {
+        let mut dots = 0;
+        while ppu.mode() == mode {
+            ppu.step(1);
+            dots += 1;
+        }
+        dots
+    }
+
+    // Runs to the start of mode 3 on the current line, which on the first line after switching
+    // the LCD on comes after mode 0 rather than OAM scan
+    fn run_to_drawing(ppu: &mut Ppu) {
+        while ppu.mode() != Mode::Drawing {
+            ppu.step(1);
+        }
+    }
+
+    #[test]
+    fn test_mode_sequence_and_lengths() {
+        let mut ppu = ppu_with_control(0x91);
+        assert_eq!(dots_in_mode(&mut ppu, Mode::HBlank), 80);
+        assert_eq!(dots_in_mode(&mut ppu, Mode::Drawing), 172);
+        assert_eq!(dots_in_mode(&mut ppu, Mode::HBlank), 204);
+        assert_eq!(ppu.read(LCD_Y_REGISTER as u16), 1);
+        assert_eq!(ppu.read(LCD_STATUS_REGISTER as u16), 0x82);
+        assert_eq!(dots_in_mode(&mut ppu, Mode::OamScan), 80);
+        assert_eq!(dots_in_mode(&mut ppu, Mode::Drawing), 172);
+    }
+
+    #[test]
+    fn test_drawing_gets_longer() {
+        let mut ppu = ppu_with_control(0xF3);
+        ppu.write(SCROLL_X_REGISTER as u16, 3);
+        ppu.write(WINDOW_X_REGISTER as u16, 7);
+        place_sprite(&mut ppu, 0, 16, 0, 0, 0);
+        run_to_drawing(&mut ppu);
+        assert_eq!(dots_in_mode(&mut ppu, Mode::Drawing), 172 + 3 + 6 + 8);
+    }
+
+    #[test]
+    fn test_line_compare_interrupt() {
+        let mut ppu = ppu_with_control(0x91);
+        ppu.write(LCD_Y_COMPARE_REGISTER as u16, 2);
+        ppu.write(LCD_STATUS_REGISTER as u16, LY_COMPARE_INTERRUPT);
+        assert!(!ppu.step(228).stat);
+        assert!(!ppu.step(228).stat);
+        assert!(!ppu.step(228).stat);
+        assert!(ppu.step(228).stat);
+        assert_eq!(ppu.read(LCD_STATUS_REGISTER as u16) & LY_COMPARE_FLAG, LY_COMPARE_FLAG);
+        // Writing LYC to the current line raises it straight away
+        ppu.step(228);
+        assert!(!ppu.write(LCD_Y_COMPARE_REGISTER as u16, 3));
+        assert!(ppu.write(LCD_Y_COMPARE_REGISTER as u16, 2));
+    }
+
+    #[test]
+    fn test_ly_wraps_early_on_last_line() {
+        let mut ppu = ppu_with_control(0x91);
+        for _ in 0..153 {
+            ppu.step(228);
+            ppu.step(228);
+        }
+        assert_eq!(ppu.read(LCD_Y_REGISTER as u16), 153);
+        ppu.step(4);
+        assert_eq!(ppu.read(LCD_Y_REGISTER as u16), 0);
+        assert_eq!(ppu.mode(), Mode::VBlank);
+        // LYC=0 matches from there rather than from the next frame
+        assert_eq!(ppu.read(LCD_STATUS_REGISTER as u16) & LY_COMPARE_FLAG, LY_COMPARE_FLAG);
+    }
+
+    #[test]
+    fn test_stat_blocking() {
+        let mut ppu = ppu_with_control(0x91);
+        ppu.write(LCD_STATUS_REGISTER as u16, HBLANK_INTERRUPT | OAM_SCAN_INTERRUPT);
+        // HBlank raises the line, and OAM scan follows straight on so there's no new rising edge
+        let mut interrupts = 0;
+        for _ in 0..456 {
+            if ppu.step(1).stat {
+                interrupts += 1;
+            }
+        }
+        assert_eq!(interrupts, 1);
+
+        // With only OAM scan selected each line starts with an interrupt
+        ppu.write(LCD_STATUS_REGISTER as u16, OAM_SCAN_INTERRUPT);
+        for _ in 0..456 {
+            if ppu.step(1).stat {
+                interrupts += 1;
+            }
+        }
+        assert_eq!(interrupts, 2);
+    }
+
+    #[test]
+    fn test_background_unsigned_tile_data() {
+        let mut ppu = ppu_with_control(0x91);
+        solid_tile(&mut ppu, 0x0010, 2);
+        ppu.vram[TILE_MAP_0 + 1] = 1;
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(7, 0), 0);
+        assert_eq!(ppu.framebuffer().shade(8, 0), 2);
+        assert_eq!(ppu.framebuffer().shade(15, 7), 2);
+        assert_eq!(ppu.framebuffer().shade(16, 0), 0);
+    }
+
+    #[test]
+    fn test_background_signed_tile_data_and_second_map() {
+        let mut ppu = ppu_with_control(0x89);
+        solid_tile(&mut ppu, SIGNED_TILE_DATA - 16, 3);
+        ppu.vram[TILE_MAP_1] = 0xFF;
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(0, 0), 3);
+        assert_eq!(ppu.framebuffer().shade(8, 0), 0);
+    }
+
+    #[test]
+    fn test_scroll_wraps_around_the_map() {
+        let mut ppu = ppu_with_control(0x91);
+        solid_tile(&mut ppu, 0x0010, 1);
+        ppu.vram[TILE_MAP_0 + 31 * 32 + 31] = 1;
+        ppu.write(SCROLL_X_REGISTER as u16, 248);
+        ppu.write(SCROLL_Y_REGISTER as u16, 248);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(0, 0), 1);
+        assert_eq!(ppu.framebuffer().shade(8, 8), 0);
+    }
+
+    #[test]
+    fn test_palette_applied() {
+        let mut ppu = ppu_with_control(0x91);
+        ppu.write(BACKGROUND_PALETTE_REGISTER as u16, 0b00_00_00_11);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(0, 0), 3);
+    }
+
+    #[test]
+    fn test_window_overlays_background() {
+        let mut ppu = ppu_with_control(0xF1);
+        solid_tile(&mut ppu, 0x0010, 3);
+        ppu.vram[TILE_MAP_1] = 1;
+        ppu.write(WINDOW_X_REGISTER as u16, 7 + 80);
+        ppu.write(WINDOW_Y_REGISTER as u16, 100);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(80, 99), 0);
+        assert_eq!(ppu.framebuffer().shade(79, 100), 0);
+        assert_eq!(ppu.framebuffer().shade(80, 100), 3);
+        assert_eq!(ppu.framebuffer().shade(87, 107), 3);
+        assert_eq!(ppu.framebuffer().shade(80, 108), 0);
+    }
+
+    #[test]
+    fn test_window_y_latched_for_the_frame() {
+        for &backend in [PpuBackend::Scanline, PpuBackend::PixelFifo].iter() {
+            let mut ppu = ppu_with_backend(backend, 0xF1);
+            solid_tile(&mut ppu, 0x0010, 3);
+            for entry in ppu.vram[TILE_MAP_1..TILE_MAP_1 + 0x400].iter_mut() {
+                *entry = 1;
+            }
+            ppu.write(WINDOW_X_REGISTER as u16, 7);
+            ppu.write(WINDOW_Y_REGISTER as u16, 200);
+            run_lines(&mut ppu, 10);
+            // Moving WY to a line that has already gone by doesn't bring the window in
+            ppu.write(WINDOW_Y_REGISTER as u16, 5);
+            run_lines(&mut ppu, LINES_PER_FRAME - 10);
+            assert_eq!(ppu.framebuffer().shade(0, 20), 0, "{:?}", backend);
+
+            ppu.write(WINDOW_Y_REGISTER as u16, 0);
+            run_lines(&mut ppu, 10);
+            // Once it has matched the window stays for the rest of the frame
+            ppu.write(WINDOW_Y_REGISTER as u16, 50);
+            run_lines(&mut ppu, 144 - 10);
+            assert_eq!(ppu.framebuffer().shade(0, 20), 3, "{:?}", backend);
+            assert_eq!(ppu.framebuffer().shade(0, 100), 3, "{:?}", backend);
+        }
+    }
+
+    fn place_sprite(ppu: &mut Ppu, index: usize, y: u8, x: u8, tile: u8, attributes: u8) {
+        ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y, x, tile, attributes]);
+    }
+
+    fn sprite_ppu(lcd_control: u8) -> Ppu {
+        let mut ppu = ppu_with_control(lcd_control);
+        ppu.write(OBJECT_PALETTE_0_REGISTER as u16, IDENTITY_PALETTE);
+        ppu.write(OBJECT_PALETTE_1_REGISTER as u16, 0b00_00_00_00);
+        ppu
+    }
+
+    #[test]
+    fn test_sprite_drawn_at_offset_position() {
+        let mut ppu = sprite_ppu(0x93);
+        solid_tile(&mut ppu, 0x0010, 2);
+        place_sprite(&mut ppu, 0, 16 + 10, 8 + 20, 1, 0);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(20, 10), 2);
+        assert_eq!(ppu.framebuffer().shade(27, 17), 2);
+        assert_eq!(ppu.framebuffer().shade(19, 10), 0);
+        assert_eq!(ppu.framebuffer().shade(20, 18), 0);
+    }
+
+    #[test]
+    fn test_sprites_disabled() {
+        let mut ppu = sprite_ppu(0x91);
+        solid_tile(&mut ppu, 0x0010, 2);
+        place_sprite(&mut ppu, 0, 16, 8, 1, 0);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(0, 0), 0);
+    }
+
+    #[test]
+    fn test_tall_sprite_and_flips() {
+        let mut ppu = sprite_ppu(0x97);
+        // Tile 2 is colour 1 on its first row only, tile 3 is colour 3
+        ppu.vram[0x0020] = 0x80;
+        solid_tile(&mut ppu, 0x0030, 3);
+        place_sprite(&mut ppu, 0, 16, 8, 3, 0);
+        place_sprite(&mut ppu, 1, 16, 8 + 16, 2, SPRITE_X_FLIP | SPRITE_Y_FLIP);
+        run_frame(&mut ppu);
+        // Index 3 is rounded down to 2 in 8x16 mode
+        assert_eq!(ppu.framebuffer().shade(0, 0), 1);
+        assert_eq!(ppu.framebuffer().shade(1, 0), 0);
+        assert_eq!(ppu.framebuffer().shade(0, 15), 3);
+        // Flipped both ways the single pixel ends up in the bottom right of the top tile
+        assert_eq!(ppu.framebuffer().shade(16, 0), 3);
+        assert_eq!(ppu.framebuffer().shade(23, 15), 1);
+    }
+
+    #[test]
+    fn test_sprite_palette_and_background_priority() {
+        let mut ppu = sprite_ppu(0x93);
+        solid_tile(&mut ppu, 0x0010, 2);
+        solid_tile(&mut ppu, 0x0020, 1);
+        ppu.vram[TILE_MAP_0] = 2;
+        place_sprite(&mut ppu, 0, 16, 8, 1, SPRITE_BEHIND_BACKGROUND);
+        place_sprite(&mut ppu, 1, 16, 8 + 8, 1, SPRITE_BEHIND_BACKGROUND);
+        place_sprite(&mut ppu, 2, 16, 8 + 16, 1, SPRITE_PALETTE_1);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(0, 0), 1);
+        assert_eq!(ppu.framebuffer().shade(8, 0), 2);
+        assert_eq!(ppu.framebuffer().shade(16, 0), 0);
+    }
+
+    #[test]
+    fn test_lower_x_then_oam_index_wins() {
+        let mut ppu = sprite_ppu(0x93);
+        solid_tile(&mut ppu, 0x0010, 1);
+        solid_tile(&mut ppu, 0x0020, 2);
+        solid_tile(&mut ppu, 0x0030, 3);
+        place_sprite(&mut ppu, 0, 16, 8 + 4, 1, 0);
+        place_sprite(&mut ppu, 1, 16, 8, 2, 0);
+        place_sprite(&mut ppu, 2, 16, 8, 3, 0);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(4, 0), 2);
+        assert_eq!(ppu.framebuffer().shade(8, 0), 1);
+    }
+
+    #[test]
+    fn test_ten_sprites_per_line() {
+        let mut ppu = sprite_ppu(0x93);
+        solid_tile(&mut ppu, 0x0010, 3);
+        // Sprites off the left edge still use up a slot
+        place_sprite(&mut ppu, 0, 16, 0, 1, 0);
+        for index in 1..12 {
+            place_sprite(&mut ppu, index, 16, 8 * index as u8, 1, 0);
+        }
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(8 * 8, 0), 3);
+        assert_eq!(ppu.framebuffer().shade(9 * 8, 0), 0);
+    }
+
+    // Fills VRAM and OAM with a scene that exercises scrolling, the window and most sprite rules
+    fn busy_scene(backend: PpuBackend) -> Ppu {
+        let mut ppu = ppu_with_backend(backend, 0xF7);
+        ppu.write(OBJECT_PALETTE_0_REGISTER as u16, 0b11_10_01_00);
+        ppu.write(OBJECT_PALETTE_1_REGISTER as u16, 0b00_01_10_11);
+        for (index, byte) in ppu.vram[..0x1800].iter_mut().enumerate() {
+            *byte = (index * 7 % 251) as u8;
+        }
+        for (index, byte) in ppu.vram[TILE_MAP_0..].iter_mut().enumerate() {
+            *byte = (index % 64) as u8;
+        }
+        for index in 0..SPRITE_COUNT {
+            let attributes = (index as u8 % 16) << 4;
+            let (y, x) = (20 + index as u8 * 3, 4 + index as u8 * 5);
+            place_sprite(&mut ppu, index, y, x, index as u8, attributes);
+        }
+        // Two sprites clipped by the left edge that become due on the same dot, the later OAM
+        // entry has the lower X so it has to win
+        place_sprite(&mut ppu, 38, 150, 6, 0x21, 0x00);
+        place_sprite(&mut ppu, 39, 150, 3, 0x32, 0x00);
+        ppu.write(SCROLL_X_REGISTER as u16, 3);
+        ppu.write(SCROLL_Y_REGISTER as u16, 5);
+        ppu.write(WINDOW_X_REGISTER as u16, 50);
+        ppu.write(WINDOW_Y_REGISTER as u16, 60);
+        ppu
+    }
+
+    #[test]
+    fn test_backends_agree_on_a_static_scene() {
+        let mut scanline = busy_scene(PpuBackend::Scanline);
+        let mut fifo = busy_scene(PpuBackend::PixelFifo);
+        run_frame(&mut scanline);
+        run_frame(&mut fifo);
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                assert_eq!(
+                    fifo.framebuffer().shade(x, y),
+                    scanline.framebuffer().shade(x, y),
+                    "pixel {}, {}",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fifo_drawing_length() {
+        let mut ppu = ppu_with_backend(PpuBackend::PixelFifo, 0x91);
+        run_to_drawing(&mut ppu);
+        let plain = dots_in_mode(&mut ppu, Mode::Drawing);
+        assert_eq!(plain, 172);
+
+        ppu.write(SCROLL_X_REGISTER as u16, 5);
+        dots_in_mode(&mut ppu, Mode::HBlank);
+        dots_in_mode(&mut ppu, Mode::OamScan);
+        assert_eq!(dots_in_mode(&mut ppu, Mode::Drawing), plain + 5);
+    }
+
+    #[test]
+    fn test_fifo_sprite_stall_depends_on_fetcher() {
+        // A sprite waits for the background tile it lands in, so the stall shrinks from 11 dots
+        // when it starts on a tile boundary to 6 once the fetcher is nearly done
+        let expected = [11, 10, 9, 8, 7, 6, 6, 6];
+        for (offset, &stall) in expected.iter().enumerate() {
+            let mut ppu = ppu_with_backend(PpuBackend::PixelFifo, 0x93);
+            place_sprite(&mut ppu, 0, 16, 8 + 16 + offset as u8, 0x00, 0x00);
+            run_to_drawing(&mut ppu);
+            assert_eq!(dots_in_mode(&mut ppu, Mode::Drawing), 172 + stall, "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn test_fifo_palette_change_mid_line() {
+        for &backend in [PpuBackend::Scanline, PpuBackend::PixelFifo].iter() {
+            let mut ppu = ppu_with_backend(backend, 0x91);
+            solid_tile(&mut ppu, 0x0000, 1);
+            run_to_drawing(&mut ppu);
+            ppu.step(100);
+            ppu.write(BACKGROUND_PALETTE_REGISTER as u16, 0b00_00_11_00);
+            dots_in_mode(&mut ppu, Mode::Drawing);
+            let left = ppu.framebuffer().shade(0, 0);
+            let right = ppu.framebuffer().shade(159, 0);
+            match backend {
+                PpuBackend::Scanline => assert_eq!((left, right), (3, 3)),
+                PpuBackend::PixelFifo => assert_eq!((left, right), (1, 3)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_background_disabled_is_blank() {
+        let mut ppu = ppu_with_control(0x90);
+        solid_tile(&mut ppu, 0x0000, 3);
+        run_frame(&mut ppu);
+        assert_eq!(ppu.framebuffer().shade(0, 0), 0);
+    }
+}
@@ -0,0 +1,296 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::memory_bus::{SERIAL_CONTROL_REGISTER, SERIAL_DATA_REGISTER};
+
+// Internal clock bit times in CPU cycles. Counting CPU cycles means double speed doubles the
+// rate for free, as it does on hardware
+const NORMAL_BIT_CYCLES: u16 = 512;
+const FAST_BIT_CYCLES: u16 = 16;
+
+const TRANSFER_START: u8 = 1 << 7;
+// CGB only
+const FAST_CLOCK: u8 = 1 << 1;
+const INTERNAL_CLOCK: u8 = 1 << 0;
+
+// Whatever is on the other end of the link cable
+pub trait SerialPeer {
+    // We drive the clock: our byte goes out and theirs comes back
+    fn exchange(&mut self, outgoing: u8) -> u8;
+
+    // Called every step while we wait on an external clock with `outgoing` in SB. Returns the
+    // byte the other side clocked in once it has driven a transfer
+    fn poll_external(&mut self, _outgoing: u8) -> Option<u8> {
+        None
+    }
+}
+
+// No cable plugged in, the input line floats high
+pub struct NullPeer;
+
+impl SerialPeer for NullPeer {
+    fn exchange(&mut self, _outgoing: u8) -> u8 {
+        0xFF
+    }
+}
+
+// Records every byte sent with the internal clock. Clone it before connecting to keep a handle
+#[derive(Clone, Default)]
+pub struct CaptureSink {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl CaptureSink {
+    pub fn new() -> CaptureSink {
+        CaptureSink::default()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.borrow().clone()
+    }
+
+    pub fn take(&self) -> Vec<u8> {
+        self.bytes.borrow_mut().split_off(0)
+    }
+}
+
+impl SerialPeer for CaptureSink {
+    fn exchange(&mut self, outgoing: u8) -> u8 {
+        self.bytes.borrow_mut().push(outgoing);
+        0xFF
+    }
+}
+
+#[derive(Default)]
+struct LinkEnd {
+    // SB of a side that is waiting on an external clock
+    waiting: Option<u8>,
+    // What the other side clocked into us
+    received: Option<u8>,
+}
+
+// One end of a cable between two emulated machines stepped on the same thread
+pub struct LinkPort {
+    ends: Rc<RefCell<[LinkEnd; 2]>>,
+    side: usize,
+}
+
+impl LinkPort {
+    pub fn pair() -> (LinkPort, LinkPort) {
+        let ends = Rc::new(RefCell::new([LinkEnd::default(), LinkEnd::default()]));
+        (
+            LinkPort { ends: ends.clone(), side: 0 },
+            LinkPort { ends, side: 1 },
+        )
+    }
+}
+
+impl SerialPeer for LinkPort {
+    fn exchange(&mut self, outgoing: u8) -> u8 {
+        let mut ends = self.ends.borrow_mut();
+        let other = &mut ends[1 - self.side];
+        // A side that isn't ready still has its shift register clocked, but nobody will read it
+        match other.waiting.take() {
+            Some(incoming) => {
+                other.received = Some(outgoing);
+                incoming
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn poll_external(&mut self, outgoing: u8) -> Option<u8> {
+        let mut ends = self.ends.borrow_mut();
+        let end = &mut ends[self.side];
+        match end.received.take() {
+            Some(incoming) => {
+                end.waiting = None;
+                Some(incoming)
+            }
+            None => {
+                end.waiting = Some(outgoing);
+                None
+            }
+        }
+    }
+}
+
+pub struct Serial {
+    data: u8,
+    control: u8,
+    peer: Box<dyn SerialPeer>,
+    fast_clock_available: bool,
+    // Internal clock transfers get their byte up front and shift it in a bit at a time
+    incoming: u8,
+    bits_left: u8,
+    cycles: u16,
+}
+
+impl Serial {
+    pub fn new(fast_clock_available: bool) -> Serial {
+        Serial {
+            data: 0,
+            control: 0,
+            peer: Box::new(NullPeer),
+            fast_clock_available,
+            incoming: 0,
+            bits_left: 0,
+            cycles: 0,
+        }
+    }
+
+    pub fn connect(&mut self, peer: Box<dyn SerialPeer>) {
+        self.peer = peer;
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address as usize {
+            SERIAL_DATA_REGISTER => self.data,
+            SERIAL_CONTROL_REGISTER => self.control | !self.writable_control_bits(),
+            // The bus never routes other addresses here, treat them as open bus
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address as usize {
+            SERIAL_DATA_REGISTER => self.data = value,
+            SERIAL_CONTROL_REGISTER => {
+                self.control = value & self.writable_control_bits();
+                if self.transferring() && self.control & INTERNAL_CLOCK != 0 {
+                    self.incoming = self.peer.exchange(self.data);
+                    self.bits_left = 8;
+                    self.cycles = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Advances by the given number of CPU cycles, returns whether a transfer finished
+    pub fn step(&mut self, cycles: u8) -> bool {
+        if !self.transferring() {
+            return false;
+        }
+        if self.control & INTERNAL_CLOCK == 0 {
+            return match self.peer.poll_external(self.data) {
+                Some(incoming) => {
+                    self.data = incoming;
+                    self.finish_transfer()
+                }
+                None => false,
+            };
+        }
+
+        self.cycles += cycles as u16;
+        let bit_cycles = self.bit_cycles();
+        while self.cycles >= bit_cycles && self.bits_left > 0 {
+            self.cycles -= bit_cycles;
+            self.bits_left -= 1;
+            // Most significant bit first in both directions
+            let bit = (self.incoming >> self.bits_left) & 1;
+            self.data = self.data << 1 | bit;
+        }
+        if self.bits_left == 0 {
+            return self.finish_transfer();
+        }
+        false
+    }
+
+    fn finish_transfer(&mut self) -> bool {
+        self.control &= !TRANSFER_START;
+        true
+    }
+
+    fn transferring(&self) -> bool {
+        self.control & TRANSFER_START != 0
+    }
+
+    fn bit_cycles(&self) -> u16 {
+        if self.control & FAST_CLOCK != 0 {
+            FAST_BIT_CYCLES
+        } else {
+            NORMAL_BIT_CYCLES
+        }
+    }
+
+    fn writable_control_bits(&self) -> u8 {
+        if self.fast_clock_available {
+            TRANSFER_START | FAST_CLOCK | INTERNAL_CLOCK
+        } else {
+            TRANSFER_START | INTERNAL_CLOCK
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(serial: &mut Serial, cycles: usize) -> bool {
+        let mut finished = false;
+        for _ in 0..cycles / 4 {
+            finished |= serial.step(4);
+        }
+        finished
+    }
+
+    #[test]
+    fn test_internal_clock_transfer_takes_eight_bit_times() {
+        let sink = CaptureSink::new();
+        let mut serial = Serial::new(false);
+        serial.connect(Box::new(sink.clone()));
+        serial.write(SERIAL_DATA_REGISTER as u16, b'P');
+        serial.write(SERIAL_CONTROL_REGISTER as u16, 0x81);
+        assert_eq!(serial.read(SERIAL_CONTROL_REGISTER as u16), 0xFF);
+        assert_eq!(sink.bytes(), b"P");
+
+        assert!(!run(&mut serial, 8 * 512 - 4));
+        assert!(run(&mut serial, 4));
+        assert_eq!(serial.read(SERIAL_DATA_REGISTER as u16), 0xFF);
+        assert_eq!(serial.read(SERIAL_CONTROL_REGISTER as u16), 0x7F);
+        assert_eq!(sink.take(), b"P");
+        assert!(sink.bytes().is_empty());
+    }
+
+    #[test]
+    fn test_fast_clock_only_on_cgb() {
+        let mut serial = Serial::new(false);
+        serial.write(SERIAL_CONTROL_REGISTER as u16, 0x83);
+        assert!(!run(&mut serial, 8 * 16));
+        assert_eq!(serial.read(SERIAL_CONTROL_REGISTER as u16), 0xFF);
+
+        let mut serial = Serial::new(true);
+        serial.write(SERIAL_CONTROL_REGISTER as u16, 0x83);
+        assert!(run(&mut serial, 8 * 16));
+        assert_eq!(serial.read(SERIAL_CONTROL_REGISTER as u16), 0x7F);
+    }
+
+    #[test]
+    fn test_external_clock_waits_without_peer() {
+        let mut serial = Serial::new(false);
+        serial.write(SERIAL_CONTROL_REGISTER as u16, 0x80);
+        assert!(!run(&mut serial, 100_000));
+        assert_eq!(serial.read(SERIAL_CONTROL_REGISTER as u16), 0xFE);
+    }
+
+    #[test]
+    fn test_link_between_two_machines() {
+        let (first, second) = LinkPort::pair();
+        let mut master = Serial::new(false);
+        let mut slave = Serial::new(false);
+        master.connect(Box::new(first));
+        slave.connect(Box::new(second));
+
+        slave.write(SERIAL_DATA_REGISTER as u16, 0x42);
+        slave.write(SERIAL_CONTROL_REGISTER as u16, 0x80);
+        assert!(!slave.step(4));
+
+        master.write(SERIAL_DATA_REGISTER as u16, 0x99);
+        master.write(SERIAL_CONTROL_REGISTER as u16, 0x81);
+        assert!(slave.step(4));
+        assert_eq!(slave.read(SERIAL_DATA_REGISTER as u16), 0x99);
+        assert!(run(&mut master, 8 * 512));
+        assert_eq!(master.read(SERIAL_DATA_REGISTER as u16), 0x42);
+    }
+}
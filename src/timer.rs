@@ -0,0 +1,226 @@
+use crate::memory_bus::{
+    DIVIDER_REGISTER, TIMER_CONTROL_REGISTER, TIMER_COUNTER_REGISTER, TIMER_MODULO_REGISTER,
+};
+
+// The timer only works in whole M-cycles
+const M_CYCLE: u16 = 4;
+
+const TIMER_ENABLE_BIT: u8 = 0b100;
+const TIMER_CONTROL_UNUSED_BITS: u8 = 0b1111_1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reload {
+    None,
+    // TIMA overflowed and reads 0 for one M-cycle before TMA is copied in
+    Pending,
+    // The M-cycle TMA was copied in, TIMA writes are lost and TMA writes go straight through
+    Done,
+}
+
+pub struct Timer {
+    // Internal 16 bit counter, DIV is its upper byte
+    divider: u16,
+    counter: u8,
+    modulo: u8,
+    control: u8,
+    reload: Reload,
+}
+
+impl Timer {
+    pub fn new(divider: u16) -> Timer {
+        Timer {
+            divider,
+            counter: 0,
+            modulo: 0,
+            control: 0,
+            reload: Reload::None,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address as usize {
+            DIVIDER_REGISTER => (self.divider >> 8) as u8,
+            TIMER_COUNTER_REGISTER => self.counter,
+            TIMER_MODULO_REGISTER => self.modulo,
+            TIMER_CONTROL_REGISTER => self.control | TIMER_CONTROL_UNUSED_BITS,
+            // The bus never routes other addresses here, treat them as open bus
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address as usize {
+            DIVIDER_REGISTER => self.reset_divider(),
+            TIMER_COUNTER_REGISTER => match self.reload {
+                // Writing during the delay cancels the reload and the interrupt
+                Reload::Pending => {
+                    self.counter = value;
+                    self.reload = Reload::None;
+                }
+                Reload::Done => {}
+                Reload::None => self.counter = value,
+            },
+            TIMER_MODULO_REGISTER => {
+                self.modulo = value;
+                if self.reload == Reload::Done {
+                    self.counter = value;
+                }
+            }
+            TIMER_CONTROL_REGISTER => {
+                let before = self.timer_signal();
+                self.control = value & !TIMER_CONTROL_UNUSED_BITS;
+                // Turning the timer off or switching to a bit that is low is a falling edge too
+                if before && !self.timer_signal() {
+                    self.increment_counter();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Zeroes the divider, which counts as a falling edge if the selected bit was set
+    pub fn reset_divider(&mut self) {
+        let before = self.timer_signal();
+        self.divider = 0;
+        if before {
+            self.increment_counter();
+        }
+    }
+
+    // Advances by the given number of T-cycles, returns whether the timer interrupt fired
+    pub fn step(&mut self, cycles: u8) -> bool {
+        let mut interrupt = false;
+        for _ in 0..(cycles as u16 / M_CYCLE) {
+            interrupt |= self.tick();
+        }
+        interrupt
+    }
+
+    fn tick(&mut self) -> bool {
+        let interrupt = match self.reload {
+            Reload::Pending => {
+                self.counter = self.modulo;
+                self.reload = Reload::Done;
+                true
+            }
+            Reload::Done => {
+                self.reload = Reload::None;
+                false
+            }
+            Reload::None => false,
+        };
+
+        let before = self.timer_signal();
+        self.divider = self.divider.wrapping_add(M_CYCLE);
+        if before && !self.timer_signal() {
+            self.increment_counter();
+        }
+        interrupt
+    }
+
+    fn increment_counter(&mut self) {
+        let (counter, overflow) = self.counter.overflowing_add(1);
+        self.counter = counter;
+        if overflow {
+            self.reload = Reload::Pending;
+        }
+    }
+
+    // The divider bit TAC selects, ANDed with the enable bit. TIMA counts on its falling edge
+    fn timer_signal(&self) -> bool {
+        let bit = match self.control & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+        self.control & TIMER_ENABLE_BIT != 0 && self.divider & (1 << bit) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer_with_control(control: u8) -> Timer {
+        let mut timer = Timer::new(0);
+        timer.write(TIMER_CONTROL_REGISTER as u16, control);
+        timer
+    }
+
+    #[test]
+    fn test_counts_at_selected_rate() {
+        let mut timer = timer_with_control(0b101);
+        timer.step(16);
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 1);
+        timer.step(32);
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 3);
+
+        let mut timer = timer_with_control(0b100);
+        for _ in 0..255 {
+            timer.step(4);
+        }
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 0);
+        timer.step(4);
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 1);
+    }
+
+    #[test]
+    fn test_disabled_timer_does_not_count() {
+        let mut timer = timer_with_control(0b001);
+        timer.step(200);
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 0);
+        assert_eq!(timer.read(TIMER_CONTROL_REGISTER as u16), 0xf9);
+    }
+
+    #[test]
+    fn test_overflow_reloads_after_one_m_cycle() {
+        let mut timer = timer_with_control(0b101);
+        timer.write(TIMER_MODULO_REGISTER as u16, 0xab);
+        timer.write(TIMER_COUNTER_REGISTER as u16, 0xff);
+        assert!(!timer.step(16));
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 0x00);
+        assert!(timer.step(4));
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 0xab);
+    }
+
+    #[test]
+    fn test_tima_write_during_delay_cancels_reload() {
+        let mut timer = timer_with_control(0b101);
+        timer.write(TIMER_MODULO_REGISTER as u16, 0xab);
+        timer.write(TIMER_COUNTER_REGISTER as u16, 0xff);
+        timer.step(16);
+        timer.write(TIMER_COUNTER_REGISTER as u16, 0x12);
+        assert!(!timer.step(4));
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 0x12);
+    }
+
+    #[test]
+    fn test_writes_on_reload_cycle() {
+        let mut timer = timer_with_control(0b101);
+        timer.write(TIMER_MODULO_REGISTER as u16, 0xab);
+        timer.write(TIMER_COUNTER_REGISTER as u16, 0xff);
+        timer.step(20);
+        timer.write(TIMER_COUNTER_REGISTER as u16, 0x12);
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 0xab);
+        timer.write(TIMER_MODULO_REGISTER as u16, 0x34);
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 0x34);
+    }
+
+    #[test]
+    fn test_divider_reset_is_a_falling_edge() {
+        let mut timer = timer_with_control(0b101);
+        timer.step(8);
+        timer.write(DIVIDER_REGISTER as u16, 0x00);
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 1);
+        assert_eq!(timer.read(DIVIDER_REGISTER as u16), 0);
+    }
+
+    #[test]
+    fn test_disabling_while_bit_set_increments() {
+        let mut timer = timer_with_control(0b101);
+        timer.step(8);
+        timer.write(TIMER_CONTROL_REGISTER as u16, 0b001);
+        assert_eq!(timer.read(TIMER_COUNTER_REGISTER as u16), 1);
+    }
+}
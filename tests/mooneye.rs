@@ -0,0 +1,59 @@
+// Runs Mooneye test suite ROMs from test_roms/mooneye. The ROMs aren't in the repository, get
+// them from https://gekkio.fi/files/mooneye-test-suite/ and run `cargo test -- --ignored`
+use lib_rust_boi::cpu::CPU;
+use lib_rust_boi::serial::CaptureSink;
+
+use std::path::Path;
+
+// A passing test sends the Fibonacci numbers over serial, a failing one sends 0x42 six times
+const PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const FAIL: [u8; 6] = [0x42; 6];
+
+// Every test finishes well within this many emulated seconds
+const TIMEOUT_CYCLES: u64 = 30 * 4_194_304;
+
+fn run_mooneye(name: &str) {
+    let path = Path::new("test_roms/mooneye").join(format!("{}.gb", name));
+    let rom = std::fs::read(&path)
+        .unwrap_or_else(|error| panic!("Could not read {}: {}", path.display(), error));
+    let mut cpu = CPU::new(None, rom).unwrap();
+    let sink = CaptureSink::new();
+    cpu.connect_serial(Box::new(sink.clone()));
+
+    let mut cycles: u64 = 0;
+    while sink.bytes().len() < PASS.len() && cycles < TIMEOUT_CYCLES {
+        cycles += cpu.step().unwrap_or_else(|error| panic!("{}: {}", name, error)) as u64;
+    }
+    let output = sink.bytes();
+    assert_ne!(output, FAIL, "{} reported a failure", name);
+    assert_eq!(output, PASS, "{} did not finish", name);
+}
+
+macro_rules! mooneye_tests {
+    ($($test:ident => $rom:expr,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the Mooneye test ROMs in test_roms/mooneye"]
+            fn $test() {
+                run_mooneye($rom);
+            }
+        )*
+    };
+}
+
+mooneye_tests! {
+    interrupts_ie_push => "acceptance/interrupts/ie_push",
+    timer_div_write => "acceptance/timer/div_write",
+    timer_rapid_toggle => "acceptance/timer/rapid_toggle",
+    timer_tim00 => "acceptance/timer/tim00",
+    timer_tim00_div_trigger => "acceptance/timer/tim00_div_trigger",
+    timer_tim01 => "acceptance/timer/tim01",
+    timer_tim01_div_trigger => "acceptance/timer/tim01_div_trigger",
+    timer_tim10 => "acceptance/timer/tim10",
+    timer_tim10_div_trigger => "acceptance/timer/tim10_div_trigger",
+    timer_tim11 => "acceptance/timer/tim11",
+    timer_tim11_div_trigger => "acceptance/timer/tim11_div_trigger",
+    timer_tima_reload => "acceptance/timer/tima_reload",
+    timer_tima_write_reloading => "acceptance/timer/tima_write_reloading",
+    timer_tma_write_reloading => "acceptance/timer/tma_write_reloading",
+}